@@ -0,0 +1,48 @@
+use std::{env, fs, path::PathBuf};
+
+// The grammar crates whose resolved versions `grammar_info` reports, with the variable each
+// version is passed in.
+const GRAMMARS: &[(&str, &str)] = &[
+    ("tree-sitter-rust", "TREE_SITTER_RUST_VERSION"),
+    ("tree-sitter-java", "TREE_SITTER_JAVA_VERSION"),
+    ("tree-sitter-python", "TREE_SITTER_PYTHON_VERSION"),
+    ("tree-sitter-cpp", "TREE_SITTER_CPP_VERSION"),
+    ("tree-sitter-go", "TREE_SITTER_GO_VERSION"),
+    ("tree-sitter-bash", "TREE_SITTER_BASH_VERSION"),
+];
+
+// Finds the lock file of the workspace being built, which sits in the manifest's directory or
+// one of its parents.  There is none when the crate is built as another project's dependency.
+fn find_lock_file() -> Option<PathBuf> {
+    let manifest_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR")?);
+    manifest_dir
+        .ancestors()
+        .map(|dir| dir.join("Cargo.lock"))
+        .find(|path| path.is_file())
+}
+
+// Reads the version the lock file resolved for a package, from its `[[package]]` entry.
+fn locked_version(lock: &str, package: &str) -> Option<String> {
+    let name = format!("name = \"{}\"", package);
+    let mut lines = lock.lines().map(str::trim);
+    lines.find(|line| *line == name)?;
+    lines
+        .find_map(|line| line.strip_prefix("version = "))
+        .map(|version| version.trim_matches('"').to_string())
+}
+
+fn main() {
+    let lock_file = find_lock_file();
+    let lock = match &lock_file {
+        Some(path) => {
+            println!("cargo:rerun-if-changed={}", path.display());
+            fs::read_to_string(path).unwrap_or_default()
+        }
+        None => String::new(),
+    };
+    println!("cargo:rerun-if-changed=build.rs");
+    for (package, var) in GRAMMARS {
+        let version = locked_version(&lock, package).unwrap_or_else(|| String::from("unknown"));
+        println!("cargo:rustc-env={}={}", var, version);
+    }
+}
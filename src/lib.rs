@@ -3,15 +3,27 @@ use serde::Serialize;
 #[cfg(test)]
 use std::ptr;
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
     ffi::OsStr,
     fmt,
     fs::{self, File},
-    io,
-    ops::Range,
-    path::PathBuf,
+    io, iter,
+    num::NonZeroUsize,
+    ops::RangeInclusive,
+    panic,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+    sync::LazyLock,
+    thread,
+    time::SystemTime,
 };
-use tree_sitter::{Language, Node, Parser, Query, QueryCursor, Range as TSRange, Tree};
+use tree_sitter::{Language, Node, Parser, Query, QueryCursor, QueryError, Range as TSRange, Tree};
+
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+pub mod sarif;
 
 pub struct Filter {
     pub start: usize,
@@ -27,30 +39,259 @@ impl Default for Filter {
     }
 }
 
+/// Restricts matching to the log statements of one source file, optionally only those
+/// within an inclusive range of lines, e.g. `src/foo.rs:100-200`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceFilter {
+    pub path: PathBuf,
+    pub lines: Option<RangeInclusive<usize>>,
+}
+
+impl SourceFilter {
+    pub fn contains(&self, src_ref: &SourceRef) -> bool {
+        Path::new(&src_ref.source_path).ends_with(&self.path)
+            && match &self.lines {
+                Some(lines) => lines.contains(&src_ref.line_no),
+                None => true,
+            }
+    }
+}
+
+impl FromStr for SourceFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_line = |line: &str| {
+            line.trim()
+                .parse::<usize>()
+                .map_err(|e| format!("invalid line number `{}`: {}", line, e))
+        };
+        match s.rsplit_once(':') {
+            // anything after the last colon that doesn't look like a line range is part of the path
+            Some((path, range)) if range.trim().starts_with(|c: char| c.is_ascii_digit()) => {
+                let lines = match range.split_once('-') {
+                    Some((start, end)) => parse_line(start)?..=parse_line(end)?,
+                    None => {
+                        let line = parse_line(range)?;
+                        line..=line
+                    }
+                };
+                Ok(SourceFilter {
+                    path: PathBuf::from(path),
+                    lines: Some(lines),
+                })
+            }
+            _ => Ok(SourceFilter {
+                path: PathBuf::from(s),
+                lines: None,
+            }),
+        }
+    }
+}
+
+pub fn filter_source_refs(src_refs: &mut Vec<SourceRef>, filter: &SourceFilter) {
+    src_refs.retain(|src_ref| filter.contains(src_ref));
+}
+
+/// Rewrites the parts of a variable's value that match `pattern`, e.g. replacing UUIDs or
+/// memory addresses with a fixed placeholder so values group together.  Parsed from
+/// `REGEX=REPLACEMENT`, where the replacement can refer to the regex's groups, e.g. `$1`.
+#[derive(Clone, Debug)]
+pub struct ValueNormalizer {
+    pub pattern: Regex,
+    pub replacement: String,
+}
+
+impl ValueNormalizer {
+    pub fn normalize<'a>(normalizers: &[ValueNormalizer], value: &'a str) -> Cow<'a, str> {
+        let mut normalized = Cow::Borrowed(value);
+        for normalizer in normalizers {
+            if let Cow::Owned(replaced) = normalizer
+                .pattern
+                .replace_all(&normalized, normalizer.replacement.as_str())
+            {
+                normalized = Cow::Owned(replaced);
+            }
+        }
+        normalized
+    }
+}
+
+impl FromStr for ValueNormalizer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // the regex is more likely than the replacement to contain a '='
+        match s.rsplit_once('=') {
+            Some((pattern, replacement)) => Ok(ValueNormalizer {
+                pattern: Regex::new(pattern)
+                    .map_err(|e| format!("invalid regex `{}`: {}", pattern, e))?,
+                replacement: replacement.to_string(),
+            }),
+            None => Err(format!("expected REGEX=REPLACEMENT, got `{}`", s)),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 enum SourceLanguage {
     Rust,
     Java,
+    Python,
+    Cpp,
+    Go,
+    Shell,
 }
 
-const IDENTS_RS: &[&str] = &["debug", "info", "warn"];
+const LANGUAGES: &[SourceLanguage] = &[
+    SourceLanguage::Rust,
+    SourceLanguage::Java,
+    SourceLanguage::Python,
+    SourceLanguage::Cpp,
+    SourceLanguage::Go,
+    SourceLanguage::Shell,
+];
+
+const IDENTS_RS: &[&str] = &["trace", "debug", "info", "warn", "error"];
 const IDENTS_JAVA: &[&str] = &["logger", "log", "fine", "debug", "info", "warn", "trace"];
+const IDENTS_PY: &[&str] = &[
+    "logger", "log", "logging", "self", "debug", "info", "warning", "error", "critical",
+];
+const IDENTS_CPP: &[&str] = &[
+    "logger", "log", "spdlog", "trace", "debug", "info", "warn", "error", "critical",
+];
+const IDENTS_GO: &[&str] = &["log", "logger", "logrus", "slog", "sugar", "zap"];
+const IDENTS_SH: &[&str] = &["echo", "log", "logger", "printf"];
 
 impl SourceLanguage {
-    fn get_query(&self) -> &str {
+    // The language of a source file, from its extension.
+    fn from_path(path: &Path) -> Option<SourceLanguage> {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("rs") => Some(SourceLanguage::Rust),
+            Some("java") => Some(SourceLanguage::Java),
+            Some("py") => Some(SourceLanguage::Python),
+            Some("cc" | "cpp" | "cxx" | "h" | "hh" | "hpp" | "hxx") => Some(SourceLanguage::Cpp),
+            Some("go") => Some(SourceLanguage::Go),
+            Some("sh" | "bash") => Some(SourceLanguage::Shell),
+            _ => None,
+        }
+    }
+
+    // The language of a script without an extension, from the interpreter its shebang line
+    // names, e.g. `#!/usr/bin/env python3` or `#!/bin/bash -e`.
+    fn from_shebang(line: &str) -> Option<SourceLanguage> {
+        let mut words = line.strip_prefix("#!")?.split_whitespace();
+        let mut interpreter = words.next()?.rsplit('/').next()?;
+        if interpreter == "env" {
+            // env's flags, like `-S`, come before the interpreter
+            interpreter = words.find(|word| !word.starts_with('-'))?;
+        }
+        // a versioned interpreter, like `python3.12`
+        match interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.') {
+            "python" => Some(SourceLanguage::Python),
+            "sh" | "bash" | "dash" | "ksh" => Some(SourceLanguage::Shell),
+            _ => None,
+        }
+    }
+
+    fn get_query(&self, options: &ExtractOptions) -> String {
         match self {
             SourceLanguage::Rust => {
-                // XXX: assumes it's a debug macro
-                r#"
-                    (macro_invocation macro: (identifier) @macro-name
+                // XXX: assumes it's one of log's level macros, the generic log macro with a
+                //      level, or a tracing span, whose name is what shows up in the log, or
+                //      the format_args! a custom logging wrapper is given.  The key-value
+                //      pairs in `debug!(peer = addr; "...")` are matched once per key by a
+                //      pattern of their own.
+                let mut query = String::from(
+                    r#"
+                    (macro_invocation macro: (identifier) @level
+                        (token_tree
+                            . (string_literal) @log (identifier)? @arguments
+                        ) (#match? @level "^(trace|debug|info|warn|error)$")
+                    )
+                    (macro_invocation macro: (identifier) @_macro-name
+                        (token_tree
+                            . (string_literal) @log (identifier)? @arguments
+                        ) (#eq? @_macro-name "format_args")
+                    )
+                    (macro_invocation macro: (identifier) @level
+                        (token_tree
+                            . (identifier) @_target . (string_literal)
+                            . (string_literal) @log (identifier)? @arguments
+                        ) (#match? @level "^(trace|debug|info|warn|error)$") (#eq? @_target "target")
+                    )
+                    (macro_invocation macro: (identifier) @level
+                        (token_tree
+                            ";" . (string_literal) @log (identifier)? @arguments
+                        ) (#match? @level "^(trace|debug|info|warn|error)$")
+                    )
+                    (macro_invocation macro: (identifier) @_macro-name
+                        (token_tree
+                            (identifier) @kv-key . "=" ";" . (string_literal) @kv-message
+                        ) (#match? @_macro-name "^(trace|debug|info|warn|error)$")
+                    )
+                    (macro_invocation macro: (identifier) @level
                         (token_tree
-                            (string_literal) @log (identifier)* @arguments
-                        ) (#eq? @macro-name "debug")
+                            (identifier) @_concat . (token_tree) @log (identifier)? @arguments
+                        ) (#match? @level "^(trace|debug|info|warn|error)$") (#eq? @_concat "concat")
                     )
-                "#
+                    (macro_invocation macro: (identifier) @_macro-name
+                        (token_tree
+                            (identifier) @_level-type . (identifier) @level
+                            . (string_literal) @log (identifier)? @arguments
+                        ) (#match? @_macro-name "^(log|span)$") (#eq? @_level-type "Level")
+                    )
+                    (macro_invocation macro: (identifier) @_macro-name
+                        (token_tree
+                            . (string_literal) @log (identifier)? @arguments
+                        ) (#match? @_macro-name "^(trace|debug|info|warn|error)_span$")
+                    )
+                "#,
+                );
+                // tracing's structured fields, which are rendered after the message as
+                // `key=value`.  The macro's tokens are split up by build_tracing_src_ref.
+                query.push_str(
+                    r#"
+                    (macro_invocation macro: (_) @tracing-macro
+                        (token_tree) @tracing-event
+                        (#match? @tracing-macro "^(tracing::)?(trace|debug|info|warn|error|event)$")
+                    )
+                "#,
+                );
+                if options.include_error_context {
+                    // anyhow's error context, which ends up in the log when the error does
+                    query.push_str(
+                        r#"
+                        (call_expression
+                            function: (field_expression field: (field_identifier) @_method)
+                            arguments: (arguments . (string_literal) @log)
+                            (#eq? @_method "context")
+                        )
+                        (call_expression
+                            function: (field_expression field: (field_identifier) @_method)
+                            arguments: (arguments
+                                (closure_expression body: [
+                                    (string_literal) @log
+                                    (macro_invocation macro: (identifier) @_format
+                                        (token_tree
+                                            . (string_literal) @log (identifier)? @arguments
+                                        ) (#eq? @_format "format")
+                                    )
+                                ])
+                            )
+                            (#eq? @_method "with_context")
+                        )
+                    "#,
+                    );
+                }
+                query
             }
             SourceLanguage::Java => {
-                r#"
+                // the built-in pattern finds a logger anywhere in the object's name, like
+                // `logService`
+                let objects = logger_objects("log(ger)?|LOG(GER)?", options);
+                let mut query = format!(
+                    r#"
                     (method_invocation 
                         object: (identifier) @object-name
                         name: (identifier) @method-name
@@ -60,10 +301,217 @@ impl SourceLanguage {
                             (string_literal) @log (this)? @this (identifier) @arguments
                             (string_literal) @log (this)? @this
                         ])
-                        (#match? @object-name "log(ger)?|LOG(GER)?")
+                        (#match? @object-name "{}")
                         (#match? @method-name "fine|debug|info|warn|trace")
                     )
-                "#
+                "#,
+                    objects
+                );
+                if !options.message_bundle.is_empty() {
+                    // the message is looked up by key, e.g. messages.getString("user.login")
+                    query.push_str(&format!(
+                        r#"
+                        (method_invocation
+                            object: (identifier) @object-name
+                            name: (identifier) @method-name
+                            arguments: (argument_list
+                                . (method_invocation
+                                    name: (identifier) @_lookup
+                                    arguments: (argument_list . (string_literal) @bundle-key .))
+                                (identifier)? @arguments)
+                            (#match? @object-name "{}")
+                            (#match? @method-name "fine|debug|info|warn|trace")
+                            (#match? @_lookup "^(get|getString)$")
+                        )
+                    "#,
+                        objects
+                    ));
+                }
+                query
+            }
+            SourceLanguage::Python => {
+                // the logging module's loggers, e.g. `logger.info(...)`, `self.log.debug(...)`
+                // or `logging.warning(...)`, with the message as the first argument.  It can
+                // be a plain string with `%` args, an f-string, or a string whose `.format()`
+                // is called with the values, whose keywords name the `{name}` placeholders.
+                let objects = logger_objects("(^|\\.)(log(ger)?|logging|LOG(GER)?)$", options);
+                let call = |arguments: &str| {
+                    format!(
+                        r#"
+                        (call
+                            function: (attribute
+                                object: (_) @_object
+                                attribute: (identifier) @_method)
+                            arguments: (argument_list . {})
+                            (#match? @_object "{}")
+                            (#match? @_method "^(debug|info|warning|warn|error|critical|exception)$")
+                        )
+                    "#,
+                        arguments, objects
+                    )
+                };
+                [
+                    r#"(string) @log (identifier)? @arguments"#,
+                    r#"(string (interpolation (identifier) @arguments)) @log"#,
+                    r#"(call
+                        function: (attribute object: (string) @log attribute: (identifier) @_format)
+                        arguments: (argument_list (identifier)? @arguments)
+                        (#eq? @_format "format"))"#,
+                    r#"(call
+                        function: (attribute object: (string) @log attribute: (identifier) @_format)
+                        arguments: (argument_list (keyword_argument name: (identifier) @arguments))
+                        (#eq? @_format "format"))"#,
+                ]
+                .iter()
+                .map(|arguments| call(arguments))
+                .collect()
+            }
+            SourceLanguage::Cpp => {
+                // XXX: assumes a function named for its level, like `spdlog::info(...)` or
+                //      `logger->warn(...)`, takes a format string.  A glog or iostream style
+                //      stream, like `LOG(INFO) << "user " << id`, has its message put together
+                //      from its operands by build_stream_src_ref.  A call given only a local
+                //      variable, like `logger.info(s)`, logs what the variable was bound to,
+                //      see get_binding_query.
+                String::from(
+                    r#"
+                    (call_expression
+                        function: [
+                            (identifier) @level
+                            (qualified_identifier name: (identifier) @level)
+                            (field_expression field: (field_identifier) @level)
+                        ]
+                        arguments: (argument_list . (string_literal) @log (identifier)? @arguments)
+                        (#match? @level "^(trace|debug|info|warn|error|critical)$")
+                    )
+                    (call_expression
+                        function: [
+                            (identifier) @level
+                            (qualified_identifier name: (identifier) @level)
+                            (field_expression field: (field_identifier) @level)
+                        ]
+                        arguments: (argument_list . (identifier) @bound .)
+                        (#match? @level "^(trace|debug|info|warn|error|critical)$")
+                    )
+                    (expression_statement
+                        (binary_expression operator: "<<") @stream
+                        (#match? @stream "^((D|V|P|SYS)?LOG(_IF|_EVERY_N|_FIRST_N)?\\s*\\(|(std::)?c(err|out|log)\\b)")
+                    )
+                "#,
+                )
+            }
+            SourceLanguage::Go => {
+                // the standard library's log and slog, logrus and zap, which all take the
+                // message first, either a printf style format, like `log.Printf(...)`, or a
+                // plain message followed by fields, like `logger.Info("msg", zap.String(...))`
+                let objects = logger_objects("^(log|slog|logrus|zap|sugar)$|[Ll]og", options);
+                format!(
+                    r#"
+                    (call_expression
+                        function: (selector_expression
+                            operand: (identifier) @_object
+                            field: (field_identifier) @_method)
+                        arguments: (argument_list
+                            . [(interpreted_string_literal) (raw_string_literal)] @log
+                            (identifier)? @arguments)
+                        (#match? @_object "{}")
+                        (#match? @_method "^((Print|Fatal|Panic)(f|ln)?|(Trace|Debug|Info|Warn|Warning|Error|DPanic)(f|w|ln)?)$")
+                    )
+                "#,
+                    objects
+                )
+            }
+            SourceLanguage::Shell => {
+                // XXX: assumes the message is the first argument to `echo`, `log` or `printf`,
+                //      the first after `echo`'s flags, or the last to `logger`, whose flags
+                //      can take values.  The variables expanded in it, and the ones expanded in
+                //      printf's arguments, are the vars, in that order.
+                let var = "[(variable_name) (special_variable_name)] @arguments";
+                let expansions = format!("[(simple_expansion {var}) (expansion {var})]");
+                let message = "[(string) (raw_string)] @log";
+                let interpolated = format!("(string {expansions}) @log");
+                let command = |commands: &str, arguments: &str| {
+                    format!(
+                        r#"
+                        (command
+                            name: (command_name (word) @_command)
+                            {}
+                            (#match? @_command "^({})$")
+                        )
+                    "#,
+                        arguments, commands
+                    )
+                };
+                let mut query = String::new();
+                for message in [message, &interpolated] {
+                    query.push_str(&command(
+                        "echo|log|printf",
+                        &format!(". argument: {message}"),
+                    ));
+                    query.push_str(&command(
+                        "echo",
+                        &format!(
+                            "argument: (word) @_flag . argument: {message} (#match? @_flag \"^-[a-zA-Z]+$\")"
+                        ),
+                    ));
+                    query.push_str(&command("logger", &format!("argument: {message} .")));
+                }
+                query.push_str(&command(
+                    "printf",
+                    &format!(
+                        ". argument: [(string) (raw_string)] @log argument: [(string {expansions}) {expansions}]"
+                    ),
+                ));
+                query
+            }
+        }
+    }
+
+    // The query for the local variables a log call can be given instead of a literal, whose
+    // `@name` is the variable and `@log` the template it was bound to, along with its
+    // `@arguments`.
+    fn get_binding_query(&self) -> Option<&str> {
+        match self {
+            // a template formatted ahead of the call, like `auto s = fmt::format("id {}", id);`
+            SourceLanguage::Cpp => Some(
+                r#"
+                (declaration
+                    declarator: (init_declarator
+                        declarator: (identifier) @name
+                        value: (call_expression
+                            function: (qualified_identifier) @_format
+                            arguments: (argument_list
+                                . (string_literal) @log (identifier)? @arguments))
+                    )
+                    (#match? @_format "^(fmt|std)::format$")
+                )
+            "#,
+            ),
+            _ => None,
+        }
+    }
+
+    fn get_placeholder_regex(&self) -> &str {
+        match self {
+            // `{{` and `}}` are escaped braces, not placeholders
+            SourceLanguage::Rust => r#"\{\{|\}\}|\{[^{}]*\}"#,
+            SourceLanguage::Java => r#"\\?\{.*?\}"#,
+            // `str.format()`'s braces, which are escaped like Rust's, and `%` conversions
+            SourceLanguage::Python => {
+                r#"\{\{|\}\}|\{[^{}]*\}|%%|%(?:\([^)]*\))?[-#0 +]*\d*(?:\.\d+)?[diouxXeEfFgGcrsa]"#
+            }
+            // {fmt}'s braces, which are escaped like Rust's, and printf's conversions
+            SourceLanguage::Cpp => {
+                r#"\{\{|\}\}|\{[^{}]*\}|%%|%[-+ #0]*(?:\*|\d+)?(?:\.(?:\*|\d+))?(?:hh|h|ll|l|j|z|t|L)?[diouxXfFeEgGaAcsp]"#
+            }
+            // printf's conversions and the variables expanded in a double quoted string, like
+            // `$app` or `${host:-localhost}`
+            SourceLanguage::Shell => {
+                r#"%%|%[-+ #0]*\d*(?:\.\d+)?[diouxXfeEgGcsbq]|\$\{[^}]*\}|\$(?:\w+|[@*#?$!-])"#
+            }
+            // fmt's verbs, like `%v`, `%+v` or `%[1]d`
+            SourceLanguage::Go => {
+                r#"%%|%[-+# 0]*(?:\[\d+\])?(?:\*|\d+)?(?:\.(?:\*|\d+))?[vTtbcdoOqxXUeEfFgGspw]"#
             }
         }
     }
@@ -72,8 +520,88 @@ impl SourceLanguage {
         match self {
             SourceLanguage::Rust => IDENTS_RS,
             SourceLanguage::Java => IDENTS_JAVA,
+            SourceLanguage::Python => IDENTS_PY,
+            SourceLanguage::Cpp => IDENTS_CPP,
+            SourceLanguage::Go => IDENTS_GO,
+            SourceLanguage::Shell => IDENTS_SH,
+        }
+    }
+
+    // A file name, a tiny program, and the one log statement the query must find in it.
+    fn get_self_check(&self) -> (&str, &str, &str) {
+        match self {
+            SourceLanguage::Rust => (
+                "self_check.rs",
+                "fn main() {\n    debug!(\"self check {}\", x);\n}\n",
+                "\"self check {}\"",
+            ),
+            SourceLanguage::Java => (
+                "SelfCheck.java",
+                "class SelfCheck {\n    void main() {\n        logger.info(\"self check\");\n    }\n}\n",
+                "\"self check\"",
+            ),
+            SourceLanguage::Python => (
+                "self_check.py",
+                "def main():\n    logger.info(\"self check %s\", x)\n",
+                "\"self check %s\"",
+            ),
+            SourceLanguage::Cpp => (
+                "self_check.cc",
+                "void run() {\n    spdlog::info(\"self check {}\", x);\n}\n",
+                "\"self check {}\"",
+            ),
+            SourceLanguage::Go => (
+                "self_check.go",
+                "package main\n\nfunc main() {\n\tlog.Printf(\"self check %d\", x)\n}\n",
+                "\"self check %d\"",
+            ),
+            SourceLanguage::Shell => (
+                "self_check.sh",
+                "main() {\n    echo \"self check $x\"\n}\n",
+                "\"self check $x\"",
+            ),
+        }
+    }
+
+    fn ts_language(&self) -> Language {
+        match self {
+            SourceLanguage::Rust => tree_sitter_rust::language(),
+            SourceLanguage::Java => tree_sitter_java::language(),
+            SourceLanguage::Python => tree_sitter_python::language(),
+            SourceLanguage::Cpp => tree_sitter_cpp::language(),
+            SourceLanguage::Go => tree_sitter_go::language(),
+            SourceLanguage::Shell => tree_sitter_bash::language(),
         }
     }
+
+    // The version of the grammar crate, as resolved in Cargo.lock when this was built.
+    fn grammar_crate_version(&self) -> &'static str {
+        match self {
+            SourceLanguage::Rust => env!("TREE_SITTER_RUST_VERSION"),
+            SourceLanguage::Java => env!("TREE_SITTER_JAVA_VERSION"),
+            SourceLanguage::Python => env!("TREE_SITTER_PYTHON_VERSION"),
+            SourceLanguage::Cpp => env!("TREE_SITTER_CPP_VERSION"),
+            SourceLanguage::Go => env!("TREE_SITTER_GO_VERSION"),
+            SourceLanguage::Shell => env!("TREE_SITTER_BASH_VERSION"),
+        }
+    }
+}
+
+// The regex a query tests the name of a logger object with, the built-in pattern or one of
+// the extra loggers, which are whole names, not patterns.  It's escaped for the query's
+// string literal, which has escapes of its own.
+fn logger_objects(builtin: &str, options: &ExtractOptions) -> String {
+    let mut objects = String::from(builtin);
+    if !options.loggers.is_empty() {
+        let loggers = options
+            .loggers
+            .iter()
+            .map(|logger| regex::escape(logger))
+            .collect::<Vec<String>>()
+            .join("|");
+        objects.push_str(&format!("|^(?:{})$", loggers));
+    }
+    objects.replace('\\', "\\\\").replace('"', "\\\"")
 }
 
 pub struct CodeSource {
@@ -82,68 +610,328 @@ pub struct CodeSource {
     buffer: String,
 }
 
-const SUPPORTED_EXTS: &[&str] = &["java", "rs"];
+const SUPPORTED_EXTS: &[&str] = &[
+    "bash", "cc", "cpp", "cxx", "go", "h", "hh", "hpp", "hxx", "java", "py", "rs", "sh",
+];
+
+// Dates with an optional time in (roughly) ISO-8601 form, like 2025-04-10T22:12:52.123Z
+const TIMESTAMP_REGEX: &str =
+    r#"\d{4}-\d{2}-\d{2}(?:[T ]\d{2}:\d{2}(?::\d{2}(?:[.,]\d+)?)?(?:Z|[+-]\d{2}:?\d{2})?)?"#;
+
+// What a timestamp from the source can look like once a collector reformats it: a date or
+// time in a few words, like "Apr 10, 2025 22:12:52" or "Thu Apr 10 22:12:52 UTC 2025", that
+// has a digit and is bounded so a timestamp ending a message doesn't match any text at all
+const REFORMATTED_TIMESTAMP_REGEX: &str =
+    r#"(?:[A-Za-z]+,? ){0,2}\d[0-9A-Za-z:./+\-]*(?:,? [0-9A-Za-z:./+\-]+){0,3}"#;
+
+// The level words a log line's header names its level with, like "WARN" or "[error]"
+const LINE_LEVEL_REGEX: &str =
+    r#"(?i)\b(?:trace|debug|info|warn(?:ing)?|error|fatal|severe|critical)\b"#;
+
+// A thread name in a header, like "[main]" in "2024-05-09 19:58:53 [main] INFO Server", which
+// is bracketed on its own rather than in a word like the pid in "app[42]"
+const LINE_THREAD_REGEX: &str = r#"(?:^|\s)\[([^\]\s]+)\]"#;
+
+// Terminal escape sequences, like the color in "count=\x1b[33m5\x1b[0m"
+const ANSI_REGEX: &str = r#"\x1b\[[0-9;?]*[ -/]*[@-~]"#;
+
+// syslog and journald collapse repeats into "message repeated 5 times: [ <message>]"
+const REPEATED_REGEX: &str = r#"message repeated (\d+) times: \[ ?(.*)\]$"#;
+
+// Fewer lines than this aren't worth a thread of their own in do_mappings
+const MIN_LINES_PER_THREAD: usize = 256;
+
+const CLASS_INIT_NAME: &str = "<clinit>";
+const INSTANCE_INIT_NAME: &str = "<init>";
+const GLOBAL_SCOPE_NAME: &str = "<global>";
 
 impl CodeSource {
-    fn new(path: PathBuf, mut input: Box<dyn io::Read>) -> CodeSource {
-        let language = match path.extension() {
-            Some(ext) => match ext.to_str().unwrap() {
-                "rs" => SourceLanguage::Rust,
-                "java" => SourceLanguage::Java,
-                _ => panic!("Unsupported language"),
-            },
-            None => panic!("No extension"),
-        };
+    #[cfg(test)]
+    fn new(path: PathBuf, input: Box<dyn io::Read>) -> CodeSource {
+        CodeSource::read(path, input).expect("can read source")
+    }
+
+    fn read(path: PathBuf, mut input: Box<dyn io::Read>) -> io::Result<CodeSource> {
         let mut buffer = String::new();
-        input.read_to_string(&mut buffer).expect("can read source");
-        CodeSource {
+        input.read_to_string(&mut buffer)?;
+        // a script without an extension names its language in its shebang
+        let language = SourceLanguage::from_path(&path)
+            .or_else(|| buffer.lines().next().and_then(SourceLanguage::from_shebang))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unsupported language"))?;
+        Ok(CodeSource {
             language,
             filename: path.to_string_lossy().to_string(),
             buffer,
-        }
+        })
     }
 
     fn ts_language(&self) -> Language {
-        match self.language {
-            SourceLanguage::Rust => tree_sitter_rust::language(),
-            SourceLanguage::Java => tree_sitter_java::language(),
+        self.language.ts_language()
+    }
+}
+
+/// The tree-sitter grammar bundled for a supported language.
+pub struct GrammarInfo {
+    pub language: String,
+    pub abi_version: usize,
+    /// The version of the grammar's crate, e.g. `0.21.2` for tree-sitter-rust, or `unknown`
+    /// when the build had no lock file to read it from.
+    pub crate_version: &'static str,
+}
+
+/// Describes the bundled grammars, which helps diagnose parse failures that come from a
+/// grammar not understanding newer syntax.
+pub fn grammar_info() -> Vec<GrammarInfo> {
+    LANGUAGES
+        .iter()
+        .map(|language| GrammarInfo {
+            language: format!("{:?}", language),
+            abi_version: language.ts_language().version(),
+            crate_version: language.grammar_crate_version(),
+        })
+        .collect()
+}
+
+/// Runs each language's built-in query against a tiny embedded program and returns an
+/// error naming the language if it no longer extracts the expected log statement, so a
+/// grammar upgrade that renames or reorders nodes fails loudly instead of matching nothing.
+pub fn self_check() -> Result<(), String> {
+    for language in LANGUAGES {
+        let (filename, source, expected) = language.get_self_check();
+        let options = ExtractOptions {
+            include_error_context: true,
+            ..ExtractOptions::default()
+        };
+        let query = language.get_query(&options);
+        Query::new(&language.ts_language(), &query).map_err(|e| {
+            format!(
+                "self check failed: the {:?} query does not compile against its grammar: {}",
+                language, e
+            )
+        })?;
+        let code = CodeSource::read(PathBuf::from(filename), Box::new(source.as_bytes()))
+            .map_err(|e| format!("self check failed: cannot read {}: {}", filename, e))?;
+        let src_refs = extract_logging(&mut [code]);
+        let found = src_refs
+            .iter()
+            .map(|src_ref| src_ref.text.as_str())
+            .collect::<Vec<&str>>();
+        if found != [expected] {
+            return Err(format!(
+                "self check failed: the {:?} query extracted {:?} instead of [{:?}]",
+                language, found, expected
+            ));
         }
     }
+    Ok(())
 }
 
-pub fn find_code(sources: &str) -> Vec<CodeSource> {
+/// Finds the code under a root, a file or a directory.  Only a root that doesn't exist is an
+/// error; a file or directory that can't be read, the root included, is added to `skipped`.
+pub fn find_code(sources: &str, skipped: &mut Vec<String>) -> io::Result<Vec<CodeSource>> {
     let mut srcs = vec![];
-    let meta = fs::metadata(sources).expect("can read file metadata");
+    let meta = fs::metadata(sources)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", sources, e)))?;
     if meta.is_file() {
         let path = PathBuf::from(sources);
-        try_add_file(path, &mut srcs);
-    } else {
-        walk_dir(PathBuf::from(sources), &mut srcs).expect("can traverse directory");
+        try_add_file(path, &mut srcs, skipped);
+    } else if let Err(e) = walk_dir(PathBuf::from(sources), &mut srcs, skipped) {
+        skipped.push(format!("{}: {}", sources, e));
+    }
+    // read_dir() makes no promises about order, so sort to keep the output reproducible
+    srcs.sort_by(|a, b| a.filename.cmp(&b.filename));
+    Ok(srcs)
+}
+
+/// Finds the code under each of the roots.  Roots can overlap, like a directory and one of
+/// its subdirectories, in which case each file is indexed once under the path the first root
+/// to reach it gives.
+pub fn find_code_in(roots: &[String], skipped: &mut Vec<String>) -> io::Result<Vec<CodeSource>> {
+    let found = roots
+        .iter()
+        .map(|root| find_code(root, skipped))
+        .collect::<io::Result<_>>()?;
+    Ok(merge_roots(found))
+}
+
+/// What the code under some roots looks like, going by the modification time and length of
+/// each file `find_code_in` would read, to tell that it changed without reading it again.
+#[derive(Debug, Default, PartialEq)]
+pub struct SourceStamps(BTreeMap<PathBuf, (Option<SystemTime>, u64)>);
+
+impl SourceStamps {
+    pub fn take(roots: &[String]) -> SourceStamps {
+        let mut stamps = SourceStamps::default();
+        for root in roots {
+            stamps.add(Path::new(root));
+        }
+        stamps
+    }
+
+    // a file or directory that can't be read is left out, like find_code skips it
+    fn add(&mut self, path: &Path) {
+        let Ok(metadata) = fs::metadata(path) else {
+            return;
+        };
+        if metadata.is_dir() {
+            for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+                self.add(&entry.path());
+            }
+        } else if metadata.is_file() && is_source(path) {
+            self.0.insert(
+                path.to_path_buf(),
+                (metadata.modified().ok(), metadata.len()),
+            );
+        }
+    }
+}
+
+/// Like `find_code_in`, but reads the code as it was at a git revision, e.g. a release tag, so
+/// old logs map to the code that emitted them.  The roots must be in a git work tree.
+pub fn find_code_at_rev(
+    roots: &[String],
+    rev: &str,
+    skipped: &mut Vec<String>,
+) -> Result<Vec<CodeSource>, String> {
+    let found = roots
+        .iter()
+        .map(|root| find_code_in_git(root, rev, skipped))
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(merge_roots(found))
+}
+
+fn merge_roots(found: Vec<Vec<CodeSource>>) -> Vec<CodeSource> {
+    let mut seen = HashSet::new();
+    let mut srcs = vec![];
+    for code in found.into_iter().flatten() {
+        let key = fs::canonicalize(&code.filename).unwrap_or(PathBuf::from(&code.filename));
+        if seen.insert(key) {
+            srcs.push(code);
+        }
     }
+    srcs.sort_by(|a, b| a.filename.cmp(&b.filename));
     srcs
 }
 
-fn walk_dir(dir: PathBuf, srcs: &mut Vec<CodeSource>) -> io::Result<()> {
+fn find_code_in_git(
+    root: &str,
+    rev: &str,
+    skipped: &mut Vec<String>,
+) -> Result<Vec<CodeSource>, String> {
+    let root = Path::new(root);
+    // git is run from the root's directory, so the paths it lists are relative to that
+    let (dir, pathspec) = if root.is_dir() {
+        (root, OsStr::new("."))
+    } else {
+        let parent = root.parent().unwrap_or(Path::new(""));
+        (parent, root.file_name().unwrap_or(root.as_os_str()))
+    };
+    let git_dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let listing = git(
+        git_dir,
+        &["ls-tree", "-r", "--name-only", rev, "--"],
+        pathspec,
+    )?;
+    let mut srcs = vec![];
+    for relative in String::from_utf8_lossy(&listing).lines() {
+        let path = dir.join(relative);
+        let ext = path.extension().unwrap_or(OsStr::new(""));
+        let supported = SUPPORTED_EXTS.iter().any(|&supported| supported == ext);
+        // a file without an extension is only known to be a script once it's read
+        if supported || path.extension().is_none() {
+            let object = format!("{}:./{}", rev, relative);
+            let contents = git(git_dir, &["show"], OsStr::new(&object))?;
+            match CodeSource::read(path.clone(), Box::new(io::Cursor::new(contents))) {
+                Ok(code) => srcs.push(code),
+                Err(e) if supported => skipped.push(format!("{}: {}", path.display(), e)),
+                Err(_) => {}
+            }
+        }
+    }
+    srcs.sort_by(|a, b| a.filename.cmp(&b.filename));
+    Ok(srcs)
+}
+
+fn git(dir: &Path, args: &[&str], last: &OsStr) -> Result<Vec<u8>, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .arg(last)
+        .output()
+        .map_err(|e| format!("cannot run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} {} failed in {}: {}",
+            args.join(" "),
+            last.to_string_lossy(),
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+fn walk_dir(dir: PathBuf, srcs: &mut Vec<CodeSource>, skipped: &mut Vec<String>) -> io::Result<()> {
     for entry in fs::read_dir(dir)? {
         let entry = entry?;
         let path = entry.path();
-        let metadata = fs::metadata(&path)?;
+        // a file can go away, or be unreadable, while the tree is walked
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                skipped.push(format!("{}: {}", path.display(), e));
+                continue;
+            }
+        };
         if metadata.is_file() {
-            try_add_file(path, srcs);
+            try_add_file(path, srcs, skipped);
         } else if metadata.is_dir() {
-            walk_dir(path, srcs).expect("can traverse directory");
+            if let Err(e) = walk_dir(path.clone(), srcs, skipped) {
+                skipped.push(format!("{}: {}", path.display(), e));
+            }
         }
     }
     Ok(())
 }
 
-fn try_add_file(path: PathBuf, srcs: &mut Vec<CodeSource>) {
+fn try_add_file(path: PathBuf, srcs: &mut Vec<CodeSource>, skipped: &mut Vec<String>) {
+    if is_source(&path) {
+        let code =
+            File::open(&path).and_then(|input| CodeSource::read(path.clone(), Box::new(input)));
+        match code {
+            Ok(code) => srcs.push(code),
+            Err(e) => skipped.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+}
+
+// Whether a file is code in a supported language, by its extension or its shebang.
+fn is_source(path: &Path) -> bool {
     let ext = path.extension().unwrap_or(OsStr::new(""));
-    if SUPPORTED_EXTS.iter().any(|&supported| supported == ext) {
-        let input = Box::new(File::open(PathBuf::from(&path)).expect("can open file"));
-        let code = CodeSource::new(path, input);
-        srcs.push(code);
+    SUPPORTED_EXTS.iter().any(|&supported| supported == ext) || is_script(path)
+}
+
+// Whether a file without an extension is a script in a supported language, going by the
+// shebang on its first line.  Only the start of the file is read, since most such files,
+// like executables, aren't scripts.
+fn is_script(path: &Path) -> bool {
+    if path.extension().is_some() {
+        return false;
     }
+    let mut start = [0; 256];
+    let len = File::open(path)
+        .and_then(|mut file| io::Read::read(&mut file, &mut start))
+        .unwrap_or(0);
+    String::from_utf8_lossy(&start[..len])
+        .lines()
+        .next()
+        .and_then(SourceLanguage::from_shebang)
+        .is_some()
 }
 
 #[derive(Serialize)]
@@ -152,8 +940,91 @@ pub struct LogMapping<'a> {
     pub log_ref: &'a LogRef<'a>,
     #[serde(rename(serialize = "srcRef"))]
     pub src_ref: Option<&'a SourceRef>,
-    pub variables: HashMap<&'a str, &'a str>,
+    /// The share of the text `src_ref` matched that came from its literal text rather than its
+    /// placeholders, from 0 to 1, which unlike the score it was ranked with doesn't depend on
+    /// the `QualityWeights`.
+    #[serde(skip_serializing)]
+    pub confidence: Option<f64>,
+    /// What the line's header, the text before the message, says about it.
+    #[serde(skip_serializing)]
+    pub header: LineHeader<'a>,
+    pub variables: HashMap<&'a str, Cow<'a, str>>,
+    #[serde(
+        rename(serialize = "jsonValues"),
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub json_values: HashMap<&'a str, serde_json::Value>,
     pub stack: Vec<Vec<&'a SourceRef>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub callers: Vec<&'a SourceRef>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// How many log lines this one stands for, when syslog collapsed repeats of a message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeated: Option<usize>,
+    /// Set when extraction stopped at `max_statements`, or matching at `max_candidates`, so an
+    /// unmatched line may come from a statement that was never tried.
+    #[serde(
+        rename(serialize = "indexTruncated"),
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub index_truncated: bool,
+}
+
+/// The fields found in a log line's header, the text before its message.  A line that didn't
+/// map has no known header, so only a timestamp that starts it is taken, since a level or a
+/// thread could as well be a word in the message.
+#[derive(Debug, Default, PartialEq)]
+pub struct LineHeader<'a> {
+    pub timestamp: Option<&'a str>,
+    /// The level as written in the line, e.g. `WARN`.
+    pub level: Option<&'a str>,
+    pub thread: Option<&'a str>,
+}
+
+/// A mapping as one record of scalar columns, for columnar stores that don't take nested
+/// values.  Every column is written, null when the line didn't map, so each record has the
+/// same schema.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlatMapping<'a> {
+    pub line: &'a str,
+    pub timestamp: Option<&'a str>,
+    pub level: Option<&'a str>,
+    pub thread: Option<&'a str>,
+    pub matched: bool,
+    /// How much of the match came from the statement's literal text, from 0 to 1, see
+    /// `LogMapping::confidence`.
+    pub confidence: Option<f64>,
+    pub source_path: Option<&'a str>,
+    pub line_number: Option<usize>,
+    pub function_name: Option<&'a str>,
+    /// The variables as a JSON object encoded into a string, with the names in order.
+    pub variables: String,
+    pub repeated: Option<usize>,
+    pub truncated: bool,
+    pub index_truncated: bool,
+}
+
+impl<'a> LogMapping<'a> {
+    pub fn flatten(&self) -> FlatMapping<'a> {
+        let variables = self.variables.iter().collect::<BTreeMap<_, _>>();
+        FlatMapping {
+            line: self.log_ref.line,
+            timestamp: self.header.timestamp,
+            level: self.header.level,
+            thread: self.header.thread,
+            matched: self.src_ref.is_some(),
+            confidence: self.confidence,
+            source_path: self.src_ref.map(|src_ref| src_ref.source_path.as_str()),
+            line_number: self.src_ref.map(|src_ref| src_ref.line_no),
+            function_name: self.src_ref.map(|src_ref| src_ref.name.as_str()),
+            variables: serde_json::to_string(&variables).unwrap(),
+            repeated: self.repeated,
+            truncated: self.truncated,
+            index_truncated: self.index_truncated,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -163,8 +1034,11 @@ pub struct LogRef<'a> {
 
 pub struct QueryResult {
     kind: String,
+    capture: String,
+    // the captures of one match share this
+    match_no: usize,
     range: TSRange,
-    name_range: Range<usize>,
+    name: String,
 }
 
 pub struct SourceQuery<'a> {
@@ -180,7 +1054,7 @@ impl<'a> SourceQuery<'a> {
         let language = code.ts_language();
         parser
             .set_language(&language)
-            .expect(format!("Error loading {:?} grammar", language).as_str());
+            .unwrap_or_else(|_| panic!("Error loading {:?} grammar", language));
         let source = code.buffer.as_str();
         let tree = parser.parse(source, None).expect("source is parsable");
         // println!("{:?}", tree.root_node().to_sexp());
@@ -191,51 +1065,153 @@ impl<'a> SourceQuery<'a> {
         }
     }
 
-    pub fn query(&self, query: &str, node_kind: Option<&str>) -> Vec<QueryResult> {
-        let query = Query::new(&self.language, query).unwrap();
-        let filter_idx = node_kind.map_or(None, |kind| query.capture_index_for_name(kind));
+    pub fn query(
+        &self,
+        query: &str,
+        node_kind: Option<&str>,
+    ) -> Result<Vec<QueryResult>, QueryError> {
+        let query = Query::new(&self.language, query)?;
+        let filter_idx = node_kind.and_then(|kind| query.capture_index_for_name(kind));
         let mut cursor = QueryCursor::new();
-        cursor
+        let results = cursor
             .matches(&query, self.tree.root_node(), self.source.as_bytes())
-            .into_iter()
-            .flat_map(|m| m.captures)
-            .filter(|c| {
+            .enumerate()
+            .flat_map(|(match_no, m)| m.captures.iter().map(move |c| (match_no, c)))
+            // captures named with a leading `_` only exist to be tested by a predicate
+            .filter(|(_, c)| !query.capture_names()[c.index as usize].starts_with('_'))
+            .filter(|(_, c)| {
                 filter_idx.is_none() || (filter_idx.is_some() && filter_idx.unwrap() == c.index)
             })
-            .map(|c| QueryResult {
+            .map(|(match_no, c)| QueryResult {
                 kind: String::from(c.node.kind()),
+                capture: String::from(query.capture_names()[c.index as usize]),
+                match_no,
                 range: c.node.range(),
-                name_range: self.find_fn_range(c.node),
+                name: self.find_fn_name(c.node),
             })
-            .collect()
+            .collect();
+        Ok(results)
     }
 
-    fn find_fn_range(&self, node: Node) -> Range<usize> {
-        // println!("node.kind()={:?}", node.kind());
-        match node.kind() {
-            "function_item" => {
-                let range = node.child_by_field_name("name").unwrap().range();
-                range.start_byte..range.end_byte
-            }
-            "method_declaration" => {
-                let range = node.child_by_field_name("name").unwrap().range();
-                range.start_byte..range.end_byte
+    /// Resolves the local variable at `range` with `binding_query`, see
+    /// `SourceLanguage::get_binding_query`, to the captures of the nearest binding before it
+    /// in the function that uses it.  Only that one level of binding is followed, so a
+    /// variable bound to another variable resolves to nothing.
+    pub fn resolve_binding(
+        &self,
+        range: TSRange,
+        binding_query: &str,
+    ) -> Result<Vec<QueryResult>, QueryError> {
+        let query = Query::new(&self.language, binding_query)?;
+        let name = &self.source[range.start_byte..range.end_byte];
+        let used = self
+            .tree
+            .root_node()
+            .descendant_for_byte_range(range.start_byte, range.end_byte);
+        let scope = iter::successors(used, |node| node.parent())
+            .find(|node| matches!(node.kind(), "function_definition" | "lambda_expression"));
+        let Some(scope) = scope else {
+            return Ok(Vec::new());
+        };
+        let name_idx = query.capture_index_for_name("name");
+        let mut cursor = QueryCursor::new();
+        // where the nearest binding's name is, with its captures
+        let mut binding: Option<(usize, Vec<QueryResult>)> = None;
+        for m in cursor.matches(&query, scope, self.source.as_bytes()) {
+            let Some(bound) = m.captures.iter().find(|c| Some(c.index) == name_idx) else {
+                continue;
+            };
+            let start = bound.node.start_byte();
+            if &self.source[start..bound.node.end_byte()] != name
+                || bound.node.end_byte() > range.start_byte
+            {
+                continue;
             }
-            "constructor_declaration" => {
-                let range = node.child_by_field_name("name").unwrap().range();
-                range.start_byte..range.end_byte
+            // the query matches a binding once for each of its arguments, so the matches of
+            // the nearest one are merged
+            match &binding {
+                Some((nearest, _)) if *nearest > start => continue,
+                Some((nearest, _)) if *nearest == start => {}
+                _ => binding = Some((start, Vec::new())),
             }
-            "class_declaration" => {
-                let range = node.child_by_field_name("name").unwrap().range();
-                range.start_byte..range.end_byte
+            let results = &mut binding.as_mut().unwrap().1;
+            for c in m.captures {
+                let capture = query.capture_names()[c.index as usize];
+                if Some(c.index) == name_idx
+                    || capture.starts_with('_')
+                    || results.iter().any(|result| result.range == c.node.range())
+                {
+                    continue;
+                }
+                results.push(QueryResult {
+                    kind: String::from(c.node.kind()),
+                    capture: String::from(capture),
+                    match_no: 0,
+                    range: c.node.range(),
+                    name: self.find_fn_name(c.node),
+                });
             }
-            _ => {
-                let r = self.find_fn_range(node.parent().unwrap());
-                // println!("*****");
-                r
+        }
+        Ok(binding.map(|(_, results)| results).unwrap_or_default())
+    }
+
+    fn find_fn_name(&self, node: Node) -> String {
+        // println!("node.kind()={:?}", node.kind());
+        match node.kind() {
+            "function_item" => self.field_text(node, "name"),
+            "method_declaration" => self.field_text(node, "name"),
+            "constructor_declaration" => self.field_text(node, "name"),
+            "class_declaration" => self.field_text(node, "name"),
+            "function_declaration" => self.field_text(node, "name"),
+            "function_definition" => match node.child_by_field_name("name") {
+                Some(name) => self.source[name.start_byte()..name.end_byte()].to_string(),
+                // C++ names a function in its declarator, e.g. `Server::run` in
+                // `void Server::run(int id)`, which can be nested in a pointer declarator
+                None => {
+                    let mut declarator = node;
+                    while let Some(inner) = declarator.child_by_field_name("declarator") {
+                        declarator = inner;
+                    }
+                    self.source[declarator.start_byte()..declarator.end_byte()].to_string()
+                }
+            },
+            "class_definition" => self.field_text(node, "name"),
+            // Java runs static blocks and static field initializers in the class initializer
+            // and instance field initializers in every constructor, so use the JVM's names.
+            "static_initializer" => String::from(CLASS_INIT_NAME),
+            "field_declaration" => {
+                if self.has_static_modifier(node) {
+                    String::from(CLASS_INIT_NAME)
+                } else {
+                    String::from(INSTANCE_INIT_NAME)
+                }
             }
+            // Statements outside of any named scope, like a closure in a static initializer,
+            // would otherwise walk off the root of the tree.
+            _ => match node.parent() {
+                Some(parent) => self.find_fn_name(parent),
+                None => String::from(GLOBAL_SCOPE_NAME),
+            },
         }
     }
+
+    fn field_text(&self, node: Node, field: &str) -> String {
+        let range = node.child_by_field_name(field).unwrap().range();
+        self.source[range.start_byte..range.end_byte].to_string()
+    }
+
+    fn has_static_modifier(&self, node: Node) -> bool {
+        let mut cursor = node.walk();
+        let has_static = node
+            .children(&mut cursor)
+            .filter(|child| child.kind() == "modifiers")
+            .any(|modifiers| {
+                self.source[modifiers.start_byte()..modifiers.end_byte()]
+                    .split_whitespace()
+                    .any(|modifier| modifier == "static")
+            });
+        has_static
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -250,6 +1226,78 @@ pub struct SourceRef {
     #[serde(skip_serializing)]
     matcher: Regex,
     vars: Vec<String>,
+    /// The level the statement logs at, named by its macro, e.g. `warn` for `warn!(...)`, or
+    /// given to it, e.g. `Info` in `log!(Level::Info, ...)`.  It only breaks ties between
+    /// identical messages, so it isn't written out.
+    #[serde(skip_serializing)]
+    level: Option<String>,
+    /// The matcher's regex, only kept when extracting with `emit_pattern`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pattern: Option<String>,
+    /// The byte offset of each placeholder in `text`, the literal as written, so with
+    /// `column` it locates the placeholder a value came from.  Only kept when extracting with
+    /// `emit_placeholder_offsets`.
+    #[serde(
+        rename(serialize = "placeholderOffsets"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    placeholder_offsets: Option<Vec<usize>>,
+    /// Where the statement starts as an LSP position, only kept when extracting with
+    /// `emit_lsp_positions`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    position: Option<LspPosition>,
+    /// The name of the query capture the statement came from, e.g. `log` or `bundle-key`,
+    /// only kept when extracting with `emit_capture`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    capture: Option<String>,
+}
+
+/// A position the way the Language Server Protocol counts it, with a 0 based line and the
+/// number of UTF-16 code units before it on that line, instead of a byte column.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LspPosition {
+    pub line: usize,
+    pub character: usize,
+}
+
+impl LspPosition {
+    fn at(source: &str, byte_offset: usize) -> LspPosition {
+        let before = &source[..byte_offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        LspPosition {
+            line: before.matches('\n').count(),
+            character: before[line_start..].encode_utf16().count(),
+        }
+    }
+}
+
+impl SourceRef {
+    /// Builds a statement from its parts instead of extracting it, e.g. for statements kept
+    /// outside the source tree.  The language, and so the placeholder syntax of `template`,
+    /// comes from the extension of `source_path`.
+    pub fn from_parts(
+        source_path: &str,
+        line_no: usize,
+        name: &str,
+        template: &str,
+    ) -> Result<SourceRef, String> {
+        let language = SourceLanguage::from_path(Path::new(source_path))
+            .ok_or_else(|| format!("{}: unsupported language", source_path))?;
+        Ok(SourceRef {
+            source_path: source_path.to_string(),
+            line_no,
+            column: 0,
+            name: name.to_string(),
+            text: template.to_string(),
+            matcher: build_matcher(template, &language, &ExtractOptions::default()),
+            vars: Vec::new(),
+            level: None,
+            pattern: None,
+            placeholder_offsets: None,
+            position: None,
+            capture: None,
+        })
+    }
 }
 
 impl fmt::Display for SourceRef {
@@ -286,12 +1334,12 @@ pub struct Edge<'a> {
 }
 
 impl<'a> CallGraph<'a> {
-    pub fn new(sources: &'a mut Vec<CodeSource>) -> CallGraph<'a> {
+    pub fn new(sources: &'a mut [CodeSource]) -> CallGraph<'a> {
         let edges = Self::find_edges(sources);
         CallGraph { edges }
     }
 
-    fn find_edges(sources: &'a mut Vec<CodeSource>) -> Vec<Edge<'a>> {
+    fn find_edges(sources: &'a mut [CodeSource]) -> Vec<Edge<'a>> {
         let mut symbols = Vec::new();
         let edge_query = r#"
             (call_expression function: (identifier) @fn_name arguments: (arguments (_))*)
@@ -299,12 +1347,14 @@ impl<'a> CallGraph<'a> {
         for code in sources.iter() {
             if code.language == SourceLanguage::Rust {
                 let src_query = SourceQuery::new(code);
-                let results = src_query.query(edge_query, Some("fn_name"));
+                let results = src_query
+                    .query(edge_query, Some("fn_name"))
+                    .expect("the call query is valid");
 
                 for result in results {
                     let range = result.range;
                     let fn_call = &src_query.source[range.start_byte..range.end_byte];
-                    let src_ref = build_src_ref(code, result);
+                    let src_ref = build_src_ref(code, result, &ExtractOptions::default());
 
                     symbols.push(Edge {
                         to: fn_call,
@@ -317,21 +1367,100 @@ impl<'a> CallGraph<'a> {
     }
 }
 
-pub fn link_to_source<'a>(log_ref: &LogRef, src_refs: &'a Vec<SourceRef>) -> Option<&'a SourceRef> {
-    src_refs.iter().find(|&source_ref| {
-        if let Some(_) = source_ref.matcher.captures(log_ref.line) {
-            return true;
+pub fn link_to_source<'a>(log_ref: &LogRef, src_refs: &'a [SourceRef]) -> Option<&'a SourceRef> {
+    src_refs
+        .iter()
+        .find(|&source_ref| source_ref.matcher.is_match(log_ref.line))
+}
+
+/// Finds every statement that matches the log line, best first, along with its score.  The
+/// score is how much of the matched text came from the statement's literal text rather than
+/// its placeholders, so "step {} done" outranks "{} done" for "step 3 done".  Ties go to
+/// the statement with the smallest source path and line, so the winner never depends on
+/// the order of `src_refs`.
+pub fn rank_candidates<'a>(
+    log_ref: &LogRef,
+    src_refs: &'a [SourceRef],
+) -> Vec<(&'a SourceRef, i64)> {
+    rank_candidates_with(log_ref, src_refs, &QualityWeights::default())
+}
+
+/// How a statement that matches a log line is scored: each weight multiplies the number of
+/// characters matched by literal text, the number of placeholders, and the number of runs
+/// of literal text.  The default only counts literal characters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QualityWeights {
+    pub literal: i64,
+    pub placeholders: i64,
+    pub segments: i64,
+}
+
+impl Default for QualityWeights {
+    fn default() -> Self {
+        QualityWeights {
+            literal: 1,
+            placeholders: 0,
+            segments: 0,
         }
-        false
-    })
+    }
+}
+
+pub fn rank_candidates_with<'a>(
+    log_ref: &LogRef,
+    src_refs: &'a [SourceRef],
+    weights: &QualityWeights,
+) -> Vec<(&'a SourceRef, i64)> {
+    let mut ranked = src_refs
+        .iter()
+        .filter_map(|src_ref| {
+            let captures = src_ref.matcher.captures(log_ref.line)?;
+            let whole = captures.get(0)?;
+            // the level is in the header before the message, like "WARN" in "[... WARN app]"
+            let header = &log_ref.line[..whole.start()];
+            let same_level = src_ref.level.as_deref().is_some_and(|level| {
+                header
+                    .split(|c: char| !c.is_alphanumeric())
+                    .any(|word| word.eq_ignore_ascii_case(level))
+            });
+            let placeholders = captures.iter().skip(1).flatten().collect::<Vec<_>>();
+            let literal = whole.len() - placeholders.iter().map(|m| m.len()).sum::<usize>();
+            // the runs of literal text are the non-empty gaps around the placeholders
+            let mut segments = 0;
+            let mut last = whole.start();
+            for placeholder in &placeholders {
+                if placeholder.start() > last {
+                    segments += 1;
+                }
+                last = placeholder.end();
+            }
+            if whole.end() > last {
+                segments += 1;
+            }
+            let score = weights.literal * literal as i64
+                + weights.placeholders * (captures.len() - 1) as i64
+                + weights.segments * segments;
+            Some((src_ref, score, same_level))
+        })
+        .collect::<Vec<_>>();
+    // among equally good matches, prefer a statement logged at the line's level
+    ranked.sort_by(|(lhs, lhs_score, lhs_level), (rhs, rhs_score, rhs_level)| {
+        rhs_score
+            .cmp(lhs_score)
+            .then_with(|| rhs_level.cmp(lhs_level))
+            .then_with(|| (&lhs.source_path, lhs.line_no).cmp(&(&rhs.source_path, rhs.line_no)))
+    });
+    ranked
+        .into_iter()
+        .map(|(src_ref, score, _)| (src_ref, score))
+        .collect()
 }
 
 pub fn extract_variables<'a>(
-    log_line: &'a LogRef,
+    log_line: &LogRef<'a>,
     src_ref: &'a SourceRef,
 ) -> HashMap<&'a str, &'a str> {
     let mut variables = HashMap::new();
-    if src_ref.vars.len() > 0 {
+    if !src_ref.vars.is_empty() {
         if let Some(captures) = src_ref.matcher.captures(log_line.line) {
             for i in 0..captures.len() - 1 {
                 variables.insert(
@@ -345,7 +1474,7 @@ pub fn extract_variables<'a>(
     variables
 }
 
-pub fn filter_log(buffer: &String, filter: Filter) -> Vec<LogRef> {
+pub fn filter_log(buffer: &str, filter: Filter) -> Vec<LogRef<'_>> {
     let results = buffer
         .lines()
         .enumerate()
@@ -360,35 +1489,330 @@ pub fn filter_log(buffer: &String, filter: Filter) -> Vec<LogRef> {
     results
 }
 
-pub fn do_mappings<'a>(
-    log_refs: &'a Vec<LogRef>,
-    src_logs: &'a Vec<SourceRef>,
-    call_graph: &'a CallGraph,
-) -> Vec<LogMapping<'a>> {
-    log_refs
+/// Parses the variable values that hold a JSON object or array, like the payload logged by
+/// `info!("event: {}", serde_json::to_string(&e)?)`.
+pub fn parse_json_values<'a>(
+    variables: &HashMap<&'a str, &'a str>,
+) -> HashMap<&'a str, serde_json::Value> {
+    variables
         .iter()
-        .map(|log_ref| {
-            let src_ref: Option<&SourceRef> = link_to_source(&log_ref, &src_logs);
-            let variables = src_ref.map_or(HashMap::new(), |src_ref| {
-                extract_variables(&log_ref, src_ref)
-            });
-            let stack = src_ref.map_or(Vec::new(), |src_ref| {
-                find_possible_paths(src_ref, &call_graph)
-            });
-            LogMapping {
-                log_ref,
-                src_ref,
-                variables,
-                stack,
-            }
-        })
-        .collect::<Vec<LogMapping>>()
+        .filter_map(
+            |(&name, &value)| match serde_json::from_str::<serde_json::Value>(value) {
+                Ok(json @ (serde_json::Value::Object(_) | serde_json::Value::Array(_))) => {
+                    Some((name, json))
+                }
+                _ => None,
+            },
+        )
+        .collect()
 }
 
-pub fn find_possible_paths<'a>(
-    src_ref: &'a SourceRef,
-    call_graph: &'a CallGraph,
-) -> Vec<Vec<&'a SourceRef>> {
+/// Settings that control how log lines are mapped back to the source.
+#[derive(Default)]
+pub struct MappingOptions {
+    /// Also report variable values that are JSON objects or arrays as structured values.
+    pub parse_json_values: bool,
+    /// The marker a logger appends to messages it truncates, e.g. ` …\[truncated\]`.  A
+    /// trailing match is dropped before matching so the rest of the message still resolves.
+    pub truncation_marker: Option<Regex>,
+    /// Decoration wrapped around each line by the pipeline, like a container's `svc-1 | `.  A
+    /// match at the start of the line is dropped before anything else.
+    pub line_prefix: Option<Regex>,
+    /// Like `line_prefix`, but dropped from the end of the line, like a trailing ` <<<`.
+    pub line_suffix: Option<Regex>,
+    /// Applied in order to each variable's value after it is captured.
+    pub normalize_values: Vec<ValueNormalizer>,
+    /// Also report the calls to the function each matched statement is in.
+    pub callers: bool,
+    /// How to score the statements that match a line when picking the best one.
+    pub quality_weights: QualityWeights,
+    /// Whether the statements being matched against were cut off by `max_statements`, which
+    /// each mapping reports.  See `extract_logging_capped`.
+    pub index_truncated: bool,
+    /// The most statements to try each line against, the first ones in the order given, for
+    /// predictable latency on huge trees.  Statements are filtered, e.g. by
+    /// `filter_source_refs`, before they get here, so the cap only bounds what's left.  Each
+    /// mapping is flagged `index_truncated` when it drops any.
+    pub max_candidates: Option<usize>,
+}
+
+// Drops the pipeline's decoration from around the line, when it is there.
+fn strip_decoration<'a>(line: &'a str, prefix: Option<&Regex>, suffix: Option<&Regex>) -> &'a str {
+    let line = match prefix.and_then(|prefix| prefix.find(line)) {
+        Some(m) if m.start() == 0 => &line[m.end()..],
+        _ => line,
+    };
+    match suffix.and_then(|suffix| suffix.find_iter(line).find(|m| m.end() == line.len())) {
+        Some(m) => &line[..m.start()],
+        None => line,
+    }
+}
+
+// Unwraps a message that syslog collapsed the repeats of, with how many times it repeated.
+fn strip_repeated<'a>(repeated: &Regex, line: &'a str) -> (&'a str, Option<usize>) {
+    match repeated.captures(line) {
+        Some(c) => (c.get(2).unwrap().as_str(), c[1].parse().ok()),
+        None => (line, None),
+    }
+}
+
+// Splits off a truncation marker at the end of the line, reporting whether there was one.
+fn strip_truncation<'a>(line: &'a str, marker: Option<&Regex>) -> (&'a str, bool) {
+    let trailing = marker.and_then(|marker| marker.find_iter(line).find(|m| m.end() == line.len()));
+    match trailing {
+        Some(m) => (&line[..m.start()], true),
+        None => (line, false),
+    }
+}
+
+/// Maps each log line onto the statement that most likely emitted it, splitting the lines
+/// among a thread per core and keeping the mappings in the lines' order.  The `LogRef`s can
+/// come from `filter_log` or be built by the caller over lines it has already split out.
+pub fn do_mappings<'a>(
+    log_refs: &'a [LogRef],
+    src_logs: &'a [SourceRef],
+    call_graph: &'a CallGraph,
+    options: &MappingOptions,
+) -> Vec<LogMapping<'a>> {
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let chunk_size = log_refs.len().div_ceil(threads).max(MIN_LINES_PER_THREAD);
+    if log_refs.len() <= chunk_size {
+        return map_lines(log_refs, src_logs, call_graph, options).collect();
+    }
+    thread::scope(|scope| {
+        let handles = log_refs
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    map_lines(chunk, src_logs, call_graph, options).collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+            .collect()
+    })
+}
+
+/// Like `do_mappings`, but maps each line only as the iterator reaches it, so a caller
+/// reading a long or endless log can handle each mapping as it comes.
+pub fn map_lines<'a, 'o, I>(
+    log_refs: I,
+    src_logs: &'a [SourceRef],
+    call_graph: &'a CallGraph,
+    options: &'o MappingOptions,
+) -> LogMappings<'a, 'o, I::IntoIter>
+where
+    I: IntoIterator<Item = &'a LogRef<'a>>,
+{
+    LogMappings {
+        log_refs: log_refs.into_iter(),
+        src_logs,
+        call_graph,
+        options,
+        ansi: Regex::new(ANSI_REGEX).unwrap(),
+        repeated: Regex::new(REPEATED_REGEX).unwrap(),
+        timestamp: Regex::new(TIMESTAMP_REGEX).unwrap(),
+        level: Regex::new(LINE_LEVEL_REGEX).unwrap(),
+        thread: Regex::new(LINE_THREAD_REGEX).unwrap(),
+    }
+}
+
+/// The mappings of a sequence of log lines, see `map_lines`.
+pub struct LogMappings<'a, 'o, I> {
+    log_refs: I,
+    src_logs: &'a [SourceRef],
+    call_graph: &'a CallGraph<'a>,
+    options: &'o MappingOptions,
+    ansi: Regex,
+    repeated: Regex,
+    timestamp: Regex,
+    level: Regex,
+    thread: Regex,
+}
+
+impl<'a, I> Iterator for LogMappings<'a, '_, I>
+where
+    I: Iterator<Item = &'a LogRef<'a>>,
+{
+    type Item = LogMapping<'a>;
+
+    fn next(&mut self) -> Option<LogMapping<'a>> {
+        let log_ref = self.log_refs.next()?;
+        Some(self.map_line(log_ref))
+    }
+}
+
+impl<'a, I> LogMappings<'a, '_, I> {
+    fn map_line(&self, log_ref: &'a LogRef<'a>) -> LogMapping<'a> {
+        let line = strip_decoration(
+            log_ref.line,
+            self.options.line_prefix.as_ref(),
+            self.options.line_suffix.as_ref(),
+        );
+        let undecorated = line;
+        let (line, repeated) = strip_repeated(&self.repeated, line);
+        let (line, truncated) = strip_truncation(line, self.options.truncation_marker.as_ref());
+        let message = LogRef { line };
+        let (candidates, capped) = match self.options.max_candidates {
+            Some(max) if max < self.src_logs.len() => (&self.src_logs[..max], true),
+            _ => (self.src_logs, false),
+        };
+        let src_ref = rank_candidates_with(&message, candidates, &self.options.quality_weights)
+            .into_iter()
+            .next()
+            .map(|(src_ref, _)| src_ref);
+        let captures = src_ref.and_then(|src_ref| src_ref.matcher.captures(line));
+        let confidence = captures.as_ref().and_then(|captures| {
+            let whole = captures.get(0)?;
+            let placeholders = captures.iter().skip(1).flatten().map(|m| m.len());
+            let literal = whole.len() - placeholders.sum::<usize>();
+            (!whole.is_empty()).then(|| literal as f64 / whole.len() as f64)
+        });
+        // the message is a slice of the undecorated line, so the header is all that's before
+        // the statement's match, which for a collapsed repeat includes syslog's own header
+        let header = match captures.and_then(|captures| captures.get(0)) {
+            Some(m) => {
+                let offset = line.as_ptr() as usize - undecorated.as_ptr() as usize;
+                self.parse_header(&undecorated[..offset + m.start()])
+            }
+            None => self.parse_unmatched(undecorated),
+        };
+        let variables = src_ref.map_or(HashMap::new(), |src_ref| {
+            extract_variables(&message, src_ref)
+        });
+        let json_values = if self.options.parse_json_values {
+            parse_json_values(&variables)
+        } else {
+            HashMap::new()
+        };
+        let variables = variables
+            .into_iter()
+            .map(|(name, value)| {
+                // a logger can color just the values, which the literal text matches around
+                let value = match self.ansi.replace_all(value, "") {
+                    Cow::Borrowed(value) => {
+                        ValueNormalizer::normalize(&self.options.normalize_values, value)
+                    }
+                    Cow::Owned(value) => Cow::Owned(
+                        ValueNormalizer::normalize(&self.options.normalize_values, &value)
+                            .into_owned(),
+                    ),
+                };
+                (name, value)
+            })
+            .collect();
+        let stack = src_ref.map_or(Vec::new(), |src_ref| {
+            find_possible_paths(src_ref, self.call_graph)
+        });
+        let callers = match src_ref {
+            Some(src_ref) if self.options.callers => find_callers(src_ref, self.call_graph),
+            _ => Vec::new(),
+        };
+        LogMapping {
+            log_ref,
+            src_ref,
+            variables,
+            json_values,
+            stack,
+            callers,
+            truncated,
+            repeated,
+            index_truncated: self.options.index_truncated || capped,
+            confidence,
+            header,
+        }
+    }
+
+    fn parse_header<'h>(&self, header: &'h str) -> LineHeader<'h> {
+        let timestamp = self.timestamp.find(header);
+        let level = self.level.find(header);
+        // a bracketed timestamp or level is already accounted for
+        let thread = self
+            .thread
+            .captures_iter(header)
+            .filter_map(|c| c.get(1))
+            .find(|m| {
+                !timestamp.is_some_and(|t| t.start() < m.end() && m.start() < t.end())
+                    && !level.is_some_and(|l| l.range() == m.range())
+            });
+        LineHeader {
+            timestamp: timestamp.map(|m| m.as_str()),
+            level: level.map(|m| m.as_str()),
+            thread: thread.map(|m| m.as_str()),
+        }
+    }
+
+    /// Takes only a timestamp that starts the line, maybe bracketed, since without a statement
+    /// to say where the message begins any level or thread found could be part of it.
+    fn parse_unmatched<'h>(&self, line: &'h str) -> LineHeader<'h> {
+        let start = line.len() - line.trim_start_matches(['[', ' ']).len();
+        LineHeader {
+            timestamp: self
+                .timestamp
+                .find(&line[start..])
+                .filter(|m| m.start() == 0)
+                .map(|m| m.as_str()),
+            level: None,
+            thread: None,
+        }
+    }
+}
+
+/// Counts the log lines that mapped into each source file, for seeing which modules produce
+/// the most log volume.  A collapsed repeat counts as each line it stands for.
+pub fn count_matches_per_file<'a>(mappings: &[LogMapping<'a>]) -> BTreeMap<&'a str, usize> {
+    let mut counts = BTreeMap::new();
+    for mapping in mappings {
+        if let Some(src_ref) = mapping.src_ref {
+            *counts.entry(src_ref.source_path.as_str()).or_insert(0) +=
+                mapping.repeated.unwrap_or(1);
+        }
+    }
+    counts
+}
+
+/// Keeps the first mapping to each log statement, and the lines that didn't map, for a
+/// minimal set of lines that reaches every statement the whole log does.
+pub fn first_hits<'a, 'm>(mappings: &'m [LogMapping<'a>]) -> Vec<&'m LogMapping<'a>> {
+    let mut seen = HashSet::new();
+    mappings
+        .iter()
+        .filter(|mapping| match mapping.src_ref {
+            Some(src_ref) => seen.insert(src_ref as *const SourceRef),
+            None => true,
+        })
+        .collect()
+}
+
+/// Finds the log statements that no log line was mapped to, which points at dead code or
+/// at statements that never fire.
+pub fn find_unused<'a>(mappings: &[LogMapping], src_refs: &'a [SourceRef]) -> Vec<&'a SourceRef> {
+    let used = mappings
+        .iter()
+        .filter_map(|mapping| mapping.src_ref.map(|src_ref| src_ref as *const SourceRef))
+        .collect::<HashSet<*const SourceRef>>();
+    src_refs
+        .iter()
+        .filter(|&src_ref| !used.contains(&(src_ref as *const SourceRef)))
+        .collect()
+}
+
+/// Finds the calls to the function that contains `src_ref`, i.e. who could have reached it.
+pub fn find_callers<'a>(src_ref: &SourceRef, call_graph: &'a CallGraph) -> Vec<&'a SourceRef> {
+    call_graph
+        .edges
+        .iter()
+        .filter(|edge| edge.to == src_ref.name)
+        .map(|edge| &edge.via)
+        .collect()
+}
+
+pub fn find_possible_paths<'a>(
+    src_ref: &'a SourceRef,
+    call_graph: &'a CallGraph,
+) -> Vec<Vec<&'a SourceRef>> {
     let mut possible = Vec::new();
     let mains = call_graph
         .edges
@@ -428,45 +1852,241 @@ pub fn find_possible_paths<'a>(
     possible
 }
 
-pub fn extract_logging<'a>(sources: &mut Vec<CodeSource>) -> Vec<SourceRef> {
+/// Settings that control how log statements are extracted from the source code.
+#[derive(Default)]
+pub struct ExtractOptions {
+    /// Identifiers, beyond each language's built-in list, that name loggers rather than
+    /// logged values, e.g. `audit` in `audit.info(...)`.
+    pub loggers: Vec<String>,
+    /// Treat timestamps in a message's literal text as wildcards, for collectors that
+    /// reformat dates in the messages they store.
+    pub normalize_timestamps: bool,
+    /// Include the regex built for each statement when it is serialized, to see exactly
+    /// what a log line was matched against.
+    pub emit_pattern: bool,
+    /// Include where each placeholder is in a statement's literal when it is serialized, for
+    /// pointing from a value in a log line back to its placeholder in the source.
+    pub emit_placeholder_offsets: bool,
+    /// Include where each statement starts as an LSP position, in UTF-16 code units, when it
+    /// is serialized, for editors that can't use a byte column on a non-ASCII line.
+    pub emit_lsp_positions: bool,
+    /// Include the name of the query capture each statement came from when it is serialized,
+    /// to debug a query that extracts something unexpected.
+    pub emit_capture: bool,
+    /// Make the last placeholder in a message match as little as possible instead of as
+    /// much as possible, for values that tend to contain the message's trailing text.
+    pub lazy_placeholders: bool,
+    /// Also extract the messages given to anyhow's `.context(...)` and `.with_context(...)`
+    /// in Rust, since they surface in logs when the error is reported.
+    pub include_error_context: bool,
+    /// Stop extracting once this many statements are found, in file order, to sample a
+    /// huge tree without scanning all of it.
+    pub max_statements: Option<usize>,
+    /// Message templates by key, for Java that logs `messages.getString("key")` instead of
+    /// a literal.  See `parse_message_bundle`.
+    pub message_bundle: HashMap<String, String>,
+}
+
+/// Parses the `key=value` (or `key: value`) lines of a Java `.properties` message bundle,
+/// skipping blank lines and `#` or `!` comments.
+pub fn parse_message_bundle(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(['#', '!']))
+        .filter_map(|line| {
+            let (key, value) = line.split_once(['=', ':'])?;
+            Some((key.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+pub fn extract_logging(sources: &mut [CodeSource]) -> Vec<SourceRef> {
+    extract_logging_with(sources, &ExtractOptions::default())
+        .expect("the default queries are valid")
+}
+
+/// Like `extract_logging`, but with options, which can fail when a query built from them, like
+/// one naming extra loggers, doesn't compile.
+pub fn extract_logging_with(
+    sources: &mut [CodeSource],
+    options: &ExtractOptions,
+) -> Result<Vec<SourceRef>, String> {
+    extract_logging_capped(sources, options).map(|(src_refs, _)| src_refs)
+}
+
+/// Like `extract_logging_with`, but also tells whether `max_statements` cut extraction short
+/// by dropping a statement, which isn't the case when the tree fits under the cap exactly.
+pub fn extract_logging_capped(
+    sources: &mut [CodeSource],
+    options: &ExtractOptions,
+) -> Result<(Vec<SourceRef>, bool), String> {
     let mut matched = Vec::new();
     for code in sources.iter() {
+        // the files after the cap is reached are only read until one has a statement, which
+        // tells that the cap dropped something
+        if options
+            .max_statements
+            .is_some_and(|max| matched.len() > max)
+        {
+            break;
+        }
+        let file_start = matched.len();
         let src_query = SourceQuery::new(code);
-        let query = code.language.get_query();
-        let results = src_query.query(query, None);
+        let query = code.language.get_query(options);
+        let results = src_query.query(&query, None).map_err(|e| {
+            format!(
+                "the {:?} log statement query does not compile: {}",
+                code.language, e
+            )
+        })?;
+        let mut level = None;
+        let mut tracing_macro = None;
+        // the key-value keys found so far, with where the message they belong to starts
+        let mut kv_keys = Vec::new();
+        let mut kv_key = None;
+        // the match that last added or repeated a statement, and where that statement is
+        let mut statement = None;
         for result in results {
             // println!("node.kind()={:?} range={:?}", result.kind, result.range);
-            match result.kind.as_str() {
-                "string_literal" => {
-                    let src_ref = build_src_ref(code, result);
+            if result.capture == "level" {
+                // the level comes before the message it applies to
+                let range = result.range;
+                level = Some(code.buffer[range.start_byte..range.end_byte].to_string());
+                continue;
+            }
+            if result.capture == "tracing-macro" {
+                let range = result.range;
+                tracing_macro = Some(code.buffer[range.start_byte..range.end_byte].to_string());
+                continue;
+            }
+            if result.capture == "tracing-event" {
+                let tracing_macro = tracing_macro.take().unwrap_or_default();
+                matched.extend(build_tracing_src_ref(
+                    code,
+                    &result,
+                    &tracing_macro,
+                    options,
+                ));
+                continue;
+            }
+            if result.capture == "stream" {
+                matched.extend(build_stream_src_ref(code, &result, options));
+                continue;
+            }
+            if result.capture == "bound" {
+                // the statement is the template the variable was bound to, with its arguments
+                let binding_query = code.language.get_binding_query().unwrap_or_default();
+                let binding = src_query
+                    .resolve_binding(result.range, binding_query)
+                    .map_err(|e| {
+                        format!(
+                            "the {:?} local binding query does not compile: {}",
+                            code.language, e
+                        )
+                    })?;
+                let mut binding = binding.into_iter();
+                if let Some(template) = binding.next() {
+                    let mut src_ref = build_src_ref(code, template, options);
+                    src_ref.level = level.take();
+                    src_ref.vars = binding
+                        .map(|arg| {
+                            code.buffer[arg.range.start_byte..arg.range.end_byte].to_string()
+                        })
+                        .collect();
                     matched.push(src_ref);
                 }
-                "identifier" | "this" => {
+                continue;
+            }
+            if result.capture == "kv-key" {
+                let range = result.range;
+                kv_key = Some(code.buffer[range.start_byte..range.end_byte].to_string());
+                continue;
+            }
+            if result.capture == "kv-message" {
+                if let Some(key) = kv_key.take() {
+                    let kv = (result.range.start_point, key);
+                    if !kv_keys.contains(&kv) {
+                        kv_keys.push(kv);
+                    }
+                }
+                continue;
+            }
+            match result.kind.as_str() {
+                "string_literal"
+                | "string"
+                | "interpreted_string_literal"
+                | "raw_string_literal"
+                | "raw_string"
+                | "token_tree" => {
+                    // the queries but Java's, like the message bundle one, match a statement
+                    // once for each of its arguments, so only the first match adds
+                    // the statement and the rest add an argument
+                    let start = result.range.start_point;
+                    let repeated = (code.language != SourceLanguage::Java
+                        || result.capture == "bundle-key")
+                        && matched[file_start..]
+                            .last()
+                            .is_some_and(|prior: &SourceRef| {
+                                prior.line_no == start.row + 1 && prior.column == start.column
+                            });
+                    let level = level.take();
+                    let match_no = result.match_no;
+                    if !repeated {
+                        let mut src_ref = build_src_ref(code, result, options);
+                        src_ref.level = level;
+                        matched.push(src_ref);
+                    }
+                    statement = Some((match_no, matched.len() - 1));
+                }
+                "identifier" | "this" | "variable_name" | "special_variable_name" => {
                     let range = result.range;
                     let source = code.buffer.as_str();
                     let text = source[range.start_byte..range.end_byte].to_string();
                     // println!("text={} matched.len()={}", text, matched.len());
                     // check the text doesn't match any of the logging related identifiers
+                    let lowered = text.to_lowercase();
                     if code
                         .language
                         .get_identifiers()
                         .iter()
-                        .all(|&s| s != text.to_lowercase())
+                        .all(|&s| s != lowered)
+                        && options.loggers.iter().all(|s| s.to_lowercase() != lowered)
                     {
-                        let length = matched.len() - 1;
-                        let prior_result: &mut SourceRef = matched.get_mut(length).unwrap();
-                        prior_result.vars.push(text);
+                        // an argument only belongs to a statement found by the same match; a
+                        // match without a message, like `logger.info(msg, user)`, has none
+                        if let Some((match_no, index)) = statement {
+                            if match_no == result.match_no {
+                                matched[index].vars.push(text);
+                            }
+                        }
                     }
                 }
                 _ => println!("ignoring {}", result.kind),
             }
             // println!("*****");
         }
+        // the key-value pairs are named args, which follow the positional ones
+        for (start, key) in kv_keys {
+            let message = matched[file_start..]
+                .iter_mut()
+                .find(|src_ref| src_ref.line_no == start.row + 1 && src_ref.column == start.column);
+            if let Some(src_ref) = message {
+                src_ref.vars.push(key);
+            }
+        }
+        // the query's alternative patterns can yield matches out of source order
+        matched[file_start..].sort_by_key(|src_ref| (src_ref.line_no, src_ref.column));
     }
-    matched
+    let truncated = options
+        .max_statements
+        .is_some_and(|max| matched.len() > max);
+    if let Some(max) = options.max_statements {
+        matched.truncate(max);
+    }
+    Ok((matched, truncated))
 }
 
-fn build_src_ref<'a, 'q>(code: &CodeSource, result: QueryResult) -> SourceRef {
+fn build_src_ref(code: &CodeSource, result: QueryResult, options: &ExtractOptions) -> SourceRef {
     let range = result.range;
     let source = code.buffer.as_str();
     let text = source[range.start_byte..range.end_byte].to_string();
@@ -477,37 +2097,375 @@ fn build_src_ref<'a, 'q>(code: &CodeSource, result: QueryResult) -> SourceRef {
     if start == range.end_byte {
         end = range.end_byte;
     }
-    let unquoted = &source[start..end].to_string();
+    let unquoted = if matches!(
+        code.language,
+        SourceLanguage::Python | SourceLanguage::Cpp | SourceLanguage::Go | SourceLanguage::Shell
+    ) {
+        // a newline ending a printf style message ends the log line instead of being in it
+        unquote(&text).trim_end_matches("\\n").to_string()
+    } else if result.kind == "token_tree" {
+        // the arguments to `concat!`, which are joined into one format string
+        static LITERAL: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r#""((?:[^"\\]|\\.)*)""#).unwrap());
+        LITERAL
+            .captures_iter(&text)
+            .map(|c| c.get(1).unwrap().as_str())
+            .collect::<String>()
+    } else {
+        source[start..end].to_string()
+    };
+    // a message bundle key stands in for the template it names
+    let unquoted = match options.message_bundle.get(&unquoted) {
+        Some(template) if result.capture == "bundle-key" => template.clone(),
+        _ => unquoted,
+    };
     // println!("{} line {}", code.filename, line);
-    let matcher = build_matcher(unquoted);
+    let matcher = build_matcher(&unquoted, &code.language, options);
     let vars = Vec::new();
-    let name = source[result.name_range].to_string();
+    let name = result.name;
+    let capture = options.emit_capture.then_some(result.capture);
+    let placeholder_offsets = options
+        .emit_placeholder_offsets
+        .then(|| find_placeholder_offsets(&text, &code.language));
     SourceRef {
         source_path: code.filename.clone(),
         line_no: line,
         column: col,
         name,
         text,
+        pattern: options.emit_pattern.then(|| matcher.as_str().to_string()),
+        matcher,
+        vars,
+        level: None,
+        placeholder_offsets,
+        position: options
+            .emit_lsp_positions
+            .then(|| LspPosition::at(source, range.start_byte)),
+        capture,
+    }
+}
+
+// The contents of a string literal without its prefix, like the `f` of a Python f-string or
+// the `u8` of a C++ UTF-8 string, and its quotes, which can be single, double, tripled or
+// Go's backquotes.
+fn unquote(literal: &str) -> &str {
+    let quoted = literal.trim_start_matches(|c: char| c.is_ascii_alphanumeric());
+    ["\"\"\"", "'''", "\"", "'", "`"]
+        .iter()
+        .find(|quote| {
+            quoted.len() >= 2 * quote.len()
+                && quoted.starts_with(*quote)
+                && quoted.ends_with(*quote)
+        })
+        .map_or(quoted, |quote| {
+            &quoted[quote.len()..quoted.len() - quote.len()]
+        })
+}
+
+// Builds the statement for a tracing macro with structured fields, like
+// `event!(Level::INFO, user_id = %uid, "login ok for {}", name)`, whose message is matched
+// with the fields it is rendered with, i.e. `login ok for {} user_id={}`.  Macros without a
+// message, or with the log crate's `key = value;` syntax, are left to the other patterns,
+// as are those without fields unless they're called by path, like `tracing::info!`, which
+// the other patterns don't find.
+fn build_tracing_src_ref(
+    code: &CodeSource,
+    result: &QueryResult,
+    macro_name: &str,
+    options: &ExtractOptions,
+) -> Option<SourceRef> {
+    static FIELD: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"^([A-Za-z_][\w.]*)\s*=(?:[^=]|$)"#).unwrap());
+    static SHORTHAND: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"^[%?]?\s*([A-Za-z_][\w.]*)$"#).unwrap());
+    static DIRECTIVE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"^\w+\s*:(?:[^:]|$)"#).unwrap());
+    let range = result.range;
+    let tree = &code.buffer[range.start_byte..range.end_byte];
+    let segments = split_token_tree(&tree[1..tree.len() - 1])?;
+    let mut level = None;
+    let mut keys = Vec::new();
+    let mut message = None;
+    let mut args = Vec::new();
+    for (offset, segment) in segments {
+        let trimmed = segment.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if message.is_some() {
+            args.push(trimmed.to_string());
+        } else if trimmed.starts_with('"') {
+            let start =
+                range.start_byte + 1 + offset + (segment.len() - segment.trim_start().len());
+            message = Some((start, trimmed));
+        } else if let Some((_, name)) = trimmed.rsplit_once("Level::") {
+            level = Some(name.to_string());
+        } else if let Some(c) = FIELD.captures(trimmed) {
+            keys.push(c[1].to_string());
+        } else if let Some(c) = SHORTHAND.captures(trimmed) {
+            keys.push(c[1].to_string());
+        } else if !DIRECTIVE.is_match(trimmed) {
+            return None;
+        }
+    }
+    let (start, text) = message?;
+    if (keys.is_empty() && !macro_name.contains("::")) || text.len() < 2 {
+        return None;
+    }
+    // `event!` takes its level as an argument, the others are named for theirs
+    let level = match macro_name.rsplit("::").next() {
+        Some("event") | None => level,
+        Some(name) => Some(name.to_string()),
+    };
+    let template = keys
+        .iter()
+        .fold(text[1..text.len() - 1].to_string(), |acc, key| {
+            acc + &format!(" {}={{}}", key)
+        });
+    let matcher = build_matcher(&template, &code.language, options);
+    let line_start = code.buffer[..start].rfind('\n').map_or(0, |i| i + 1);
+    Some(SourceRef {
+        source_path: code.filename.clone(),
+        line_no: code.buffer[..start].matches('\n').count() + 1,
+        column: start - line_start,
+        name: result.name.clone(),
+        text: text.to_string(),
+        pattern: options.emit_pattern.then(|| matcher.as_str().to_string()),
+        matcher,
+        vars: args.into_iter().chain(keys).collect(),
+        level,
+        placeholder_offsets: options
+            .emit_placeholder_offsets
+            .then(|| find_placeholder_offsets(text, &code.language)),
+        position: options
+            .emit_lsp_positions
+            .then(|| LspPosition::at(&code.buffer, start)),
+        capture: options.emit_capture.then(|| result.capture.clone()),
+    })
+}
+
+// Builds the statement for a C++ stream, like `LOG(INFO) << "user " << id << " done"`, whose
+// message is put together from the operands after the logger: the string literals are its
+// text and any other expression a placeholder, i.e. `user {} done` with the var `id`.
+fn build_stream_src_ref(
+    code: &CodeSource,
+    result: &QueryResult,
+    options: &ExtractOptions,
+) -> Option<SourceRef> {
+    static STRINGS: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"^(?:\s*(?:u8|[uUL])?"(?:[^"\\]|\\.)*")+$"#).unwrap());
+    static LITERAL: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#""((?:[^"\\]|\\.)*)""#).unwrap());
+    // the severity glog's macros take, e.g. `INFO` in `LOG(INFO)` or `LOG_IF(WARNING, full)`
+    static SEVERITY: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"^\w*LOG\w*\s*\(\s*(?:google::(?:GLOG_)?)?([A-Z]+)\b"#).unwrap()
+    });
+    let range = result.range;
+    let stream = &code.buffer[range.start_byte..range.end_byte];
+    let mut operands = split_stream(stream).into_iter();
+    let (_, logger) = operands.next()?;
+    let level = SEVERITY.captures(logger.trim()).map(|c| c[1].to_string());
+    let mut template = String::new();
+    let mut vars = Vec::new();
+    let mut offsets = Vec::new();
+    for (offset, operand) in operands {
+        let trimmed = operand.trim();
+        if STRINGS.is_match(trimmed) {
+            // the literal text is escaped so it can't be taken for a placeholder
+            for c in LITERAL.captures_iter(trimmed) {
+                template.push_str(
+                    &c[1]
+                        .replace('{', "{{")
+                        .replace('}', "}}")
+                        .replace('%', "%%"),
+                );
+            }
+        } else if !["std::endl", "endl", "std::flush", "flush"].contains(&trimmed) {
+            template.push_str("{}");
+            vars.push(trimmed.to_string());
+            offsets.push(offset + operand.len() - operand.trim_start().len());
+        }
+    }
+    let matcher = build_matcher(&template, &code.language, options);
+    Some(SourceRef {
+        source_path: code.filename.clone(),
+        line_no: range.start_point.row + 1,
+        column: range.start_point.column,
+        name: result.name.clone(),
+        text: stream.to_string(),
+        pattern: options.emit_pattern.then(|| matcher.as_str().to_string()),
         matcher,
         vars,
+        level,
+        placeholder_offsets: options.emit_placeholder_offsets.then_some(offsets),
+        position: options
+            .emit_lsp_positions
+            .then(|| LspPosition::at(&code.buffer, range.start_byte)),
+        capture: options.emit_capture.then(|| result.capture.clone()),
+    })
+}
+
+// Splits a stream expression at the `<<`s that aren't nested or in a literal, with each
+// operand's offset.
+fn split_stream(stream: &str) -> Vec<(usize, &str)> {
+    let mut operands = Vec::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in stream.char_indices() {
+        if let Some(open) = quote {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                _ if c == open => quote = None,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            // the next operand starts after the `<<`, so its second `<` isn't split at
+            '<' if depth == 0 && i >= start && stream[i..].starts_with("<<") => {
+                operands.push((start, &stream[start..i]));
+                start = i + 2;
+            }
+            _ => {}
+        }
+    }
+    operands.push((start, &stream[start..]));
+    operands
+}
+
+// Splits a macro's tokens at the commas that aren't nested or in a string, with each piece's
+// offset.  Returns None for the log crate's `key = value; "message"` syntax.
+fn split_token_tree(tokens: &str) -> Option<Vec<(usize, &str)>> {
+    let mut segments = Vec::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in tokens.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ';' if depth == 0 => return None,
+            ',' if depth == 0 => {
+                segments.push((start, &tokens[start..i]));
+                start = i + 1;
+            }
+            _ => {}
+        }
     }
+    segments.push((start, &tokens[start..]));
+    Some(segments)
 }
 
-fn build_matcher(text: &str) -> Regex {
+fn find_placeholder_offsets(text: &str, language: &SourceLanguage) -> Vec<usize> {
+    let placeholder = Regex::new(language.get_placeholder_regex()).unwrap();
+    placeholder
+        .find_iter(text)
+        .filter(|m| !["{{", "}}", "%%"].contains(&m.as_str()))
+        .map(|m| m.start())
+        .collect()
+}
+
+fn build_matcher(text: &str, language: &SourceLanguage, options: &ExtractOptions) -> Regex {
+    let placeholder = Regex::new(language.get_placeholder_regex()).unwrap();
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut last = 0;
+    for m in placeholder.find_iter(text) {
+        literal.push_str(&text[last..m.start()]);
+        match m.as_str() {
+            "{{" => literal.push('{'),
+            "}}" => literal.push('}'),
+            "%%" => literal.push('%'),
+            _ => segments.push(std::mem::take(&mut literal)),
+        }
+        last = m.end();
+    }
+    literal.push_str(&text[last..]);
+    segments.push(literal);
     // XXX: avoid regex that are too greedy by returning a regex that
-    //      never matches anything
-    if text == "{}" || text.trim() == "" {
-        Regex::new(r#"\w\b\w"#).unwrap()
-    } else {
-        let curly_replacer = Regex::new(r#"\\?\{.*?\}"#).unwrap();
-        let escaped = curly_replacer
-            .split(text)
-            .map(|s| regex::escape(s))
-            .collect::<Vec<String>>()
-            .join(r#"(\w+)"#);
-        // println!("escaped = {}", Regex::new(&escaped).unwrap().as_str());
-        Regex::new(&escaped).unwrap()
+    //      never matches anything, like for a message that's only placeholders
+    if text.trim() == "" || segments.iter().all(|segment| segment.is_empty()) {
+        return Regex::new(r#"\w\b\w"#).unwrap();
+    }
+    // every placeholder but the last lazily matches up to the literal that follows it,
+    // so "{}-{}" splits "a-b-c" at the first '-' instead of wherever is greediest.  A
+    // value holding that literal only splits right when the rest of the template fails
+    // to match from the earlier spot, since otherwise both splits read the same.  The
+    // last one is greedy unless asked otherwise, so "path {} end" captures "x end" from
+    // "path x end end" by default and "x" with `lazy_placeholders`.
+    static TIMESTAMP: LazyLock<Regex> = LazyLock::new(|| Regex::new(TIMESTAMP_REGEX).unwrap());
+    let mut escaped = String::new();
+    for (i, segment) in segments.iter().enumerate() {
+        if i == segments.len() - 1 && i > 0 {
+            // a lazy placeholder with nothing after it would only ever capture one char
+            if options.lazy_placeholders && !segment.is_empty() {
+                escaped.push_str("(.+?)");
+            } else {
+                escaped.push_str("(.+)");
+            }
+        } else if i > 0 {
+            escaped.push_str("(.+?)");
+        }
+        if options.normalize_timestamps {
+            let pieces = TIMESTAMP
+                .split(segment)
+                .map(escape_literal)
+                .collect::<Vec<String>>();
+            escaped.push_str(&pieces.join(REFORMATTED_TIMESTAMP_REGEX));
+        } else {
+            escaped.push_str(&escape_literal(segment));
+        }
+    }
+    // println!("escaped = {}", Regex::new(&escaped).unwrap().as_str());
+    Regex::new(&escaped).unwrap()
+}
+
+// Escapes the literal text between placeholders.  Structured logs (JSON, logfmt) keep
+// C-style escapes in the message body, so each escape in the source (and any newline in a
+// multi-line literal) matches either the character itself or its escaped form.
+fn escape_literal(segment: &str) -> String {
+    let mut escaped = String::new();
+    let mut chars = segment.chars().peekable();
+    while let Some(c) = chars.next() {
+        let escape = match (c, chars.peek()) {
+            ('\n', _) | ('\\', Some(&'n')) => Some(('\n', 'n')),
+            ('\\', Some(&'t')) => Some(('\t', 't')),
+            ('\\', Some(&'"')) => Some(('"', '"')),
+            ('\\', Some(&'\\')) => Some(('\\', '\\')),
+            _ => None,
+        };
+        match escape {
+            Some((unescaped, letter)) => {
+                if c == '\\' {
+                    chars.next();
+                }
+                escaped.push_str(&format!(
+                    "(?:{}|{})",
+                    regex::escape(&unescaped.to_string()),
+                    regex::escape(&format!("\\{}", letter))
+                ));
+            }
+            None => escaped.push_str(&regex::escape(&c.to_string())),
+        }
     }
+    escaped
 }
 
 #[test]
@@ -557,7 +2515,7 @@ fn nope(i: u32) {
 #[test]
 fn test_extract_logging() {
     let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
-    let src_refs = extract_logging(&mut vec![code]);
+    let src_refs = extract_logging(&mut [code]);
     assert_eq!(src_refs.len(), 2);
     let first = &src_refs[0];
     assert_eq!(first.line_no, 7);
@@ -580,7 +2538,7 @@ fn test_link_to_source() {
         line: "[2024-02-15T03:46:44Z DEBUG stack] you're only as funky as your last cut",
     };
     let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
-    let src_refs = extract_logging(&mut vec![code]);
+    let src_refs = extract_logging(&mut [code]);
     assert_eq!(src_refs.len(), 2);
     let result = link_to_source(&log_ref, &src_refs);
     assert!(ptr::eq(result.unwrap(), &src_refs[0]));
@@ -593,10 +2551,10 @@ fn test_link_to_source_no_matches() {
     };
 
     let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
-    let src_refs = extract_logging(&mut vec![code]);
+    let src_refs = extract_logging(&mut [code]);
     assert_eq!(src_refs.len(), 2);
     let result = link_to_source(&log_ref, &src_refs);
-    assert_eq!(result.is_none(), true);
+    assert!(result.is_none());
 }
 
 #[test]
@@ -605,12 +2563,29 @@ fn test_extract_variables() {
         line: "[2024-02-15T03:46:44Z DEBUG nope] this won't match i=1",
     };
     let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
-    let src_refs = extract_logging(&mut vec![code]);
+    let src_refs = extract_logging(&mut [code]);
     assert_eq!(src_refs.len(), 2);
     let vars = extract_variables(&log_ref, &src_refs[1]);
     assert_eq!(vars.get("i"), Some(&"1"));
 }
 
+#[test]
+fn test_source_ref_from_parts() {
+    let src_ref = SourceRef::from_parts("src/worker.rs", 12, "run", "started worker {}").unwrap();
+    assert_eq!(src_ref.line_no, 12);
+    let src_refs = vec![src_ref];
+    let log_ref = LogRef {
+        line: "[2024-02-15T03:46:44Z INFO worker] started worker 3",
+    };
+    let ranked = rank_candidates(&log_ref, &src_refs);
+    assert_eq!(ranked.len(), 1);
+    assert!(ptr::eq(ranked[0].0, &src_refs[0]));
+
+    let src_ref = SourceRef::from_parts("Worker.java", 7, "run", "started worker {}").unwrap();
+    assert!(src_ref.matcher.is_match("started worker 3"));
+    assert!(SourceRef::from_parts("worker.rb", 1, "run", "started").is_err());
+}
+
 #[test]
 fn test_call_graph() {
     let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
@@ -625,6 +2600,11 @@ fn test_call_graph() {
         text: String::from("foo"),
         matcher: star_regex,
         vars: vec![],
+        level: None,
+        pattern: None,
+        placeholder_offsets: None,
+        position: None,
+        capture: None,
     };
     let star_regex = Regex::new(".*").unwrap();
     let foo_2_nope = SourceRef {
@@ -635,6 +2615,11 @@ fn test_call_graph() {
         text: String::from("nope"),
         matcher: star_regex,
         vars: vec![],
+        level: None,
+        pattern: None,
+        placeholder_offsets: None,
+        position: None,
+        capture: None,
     };
     assert_eq!(
         call_graph.edges,
@@ -668,6 +2653,11 @@ fn test_find_possible_paths() {
         text: String::from("foo"),
         matcher: star_regex,
         vars: vec![],
+        level: None,
+        pattern: None,
+        placeholder_offsets: None,
+        position: None,
+        capture: None,
     };
     let star_regex = Regex::new(".*").unwrap();
     let foo_2_nope = SourceRef {
@@ -678,24 +2668,1870 @@ fn test_find_possible_paths() {
         text: String::from("nope"),
         matcher: star_regex,
         vars: vec![],
+        level: None,
+        pattern: None,
+        placeholder_offsets: None,
+        position: None,
+        capture: None,
     };
     assert_eq!(paths, vec![vec![&foo_2_nope, &main_2_foo]])
 }
 
 #[test]
 fn test_build_matcher_needs_escape() {
-    let matcher = build_matcher("{}) {}, {}");
+    let matcher = build_matcher(
+        "{}) {}, {}",
+        &SourceLanguage::Rust,
+        &ExtractOptions::default(),
+    );
     assert_eq!(
-        Regex::new(r#"(\w+)\) (\w+), (\w+)"#).unwrap().as_str(),
+        Regex::new(r#"(.+?)\) (.+?), (.+)"#).unwrap().as_str(),
         matcher.as_str()
     );
 }
 
 #[test]
 fn test_build_matcher_mix() {
-    let matcher = build_matcher("{}) {:?}, {foo.bar}");
+    let matcher = build_matcher(
+        "{}) {:?}, {foo.bar}",
+        &SourceLanguage::Rust,
+        &ExtractOptions::default(),
+    );
+    assert_eq!(
+        Regex::new(r#"(.+?)\) (.+?), (.+)"#).unwrap().as_str(),
+        matcher.as_str()
+    );
+}
+
+#[test]
+fn test_link_to_source_escaped_body() {
+    let source = "fn main() {\n    debug!(\"line one\nline two\");\n}\n";
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs.len(), 1);
+    let log_ref = LogRef {
+        line: r#"{"level":"DEBUG","msg":"line one\nline two"}"#,
+    };
+    let result = link_to_source(&log_ref, &src_refs);
+    assert!(ptr::eq(result.unwrap(), &src_refs[0]));
+}
+
+#[test]
+fn test_build_matcher_escapes() {
+    let matcher = build_matcher(
+        r#"say \"{}\"\tnow"#,
+        &SourceLanguage::Rust,
+        &ExtractOptions::default(),
+    );
+    assert!(matcher.is_match("say \"hi\"\tnow"));
+    assert!(matcher.is_match(r#"msg="say \"hi\"\tnow""#));
+}
+
+#[test]
+fn test_source_filter_from_str() {
+    assert_eq!(
+        "src/foo.rs:100-200".parse::<SourceFilter>(),
+        Ok(SourceFilter {
+            path: PathBuf::from("src/foo.rs"),
+            lines: Some(100..=200)
+        })
+    );
+    assert_eq!(
+        "src/foo.rs:42".parse::<SourceFilter>(),
+        Ok(SourceFilter {
+            path: PathBuf::from("src/foo.rs"),
+            lines: Some(42..=42)
+        })
+    );
+    assert_eq!(
+        "src/foo.rs".parse::<SourceFilter>(),
+        Ok(SourceFilter {
+            path: PathBuf::from("src/foo.rs"),
+            lines: None
+        })
+    );
+    assert!("src/foo.rs:1-x".parse::<SourceFilter>().is_err());
+}
+
+#[test]
+fn test_filter_source_refs() {
+    let log_ref = LogRef {
+        line: "[2024-02-15T03:46:44Z DEBUG nope] this won't match i=1",
+    };
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
+    let mut src_refs = extract_logging(&mut [code]);
+    filter_source_refs(&mut src_refs, &"in-mem.rs:1-10".parse().unwrap());
+    assert_eq!(src_refs.len(), 1);
+    assert_eq!(src_refs[0].line_no, 7);
+    assert!(link_to_source(&log_ref, &src_refs).is_none());
+}
+
+#[test]
+fn test_extract_logging_is_ordered() {
+    let mut sources = find_code("examples", &mut Vec::new()).unwrap();
+    let src_refs = extract_logging(&mut sources);
+    let positions = src_refs
+        .iter()
+        .map(|src_ref| {
+            (
+                src_ref.source_path.as_str(),
+                src_ref.line_no,
+                src_ref.column,
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut sorted = positions.clone();
+    sorted.sort();
+    assert!(!positions.is_empty());
+    assert_eq!(positions, sorted);
+}
+
+#[test]
+fn test_find_code_in_overlapping_roots() {
+    let names = |sources: &[CodeSource]| {
+        sources
+            .iter()
+            .map(|code| code.filename.clone())
+            .collect::<Vec<String>>()
+    };
+    let all = find_code("examples", &mut Vec::new()).unwrap();
+    assert!(all.len() > 1);
+
+    // a root nested in another adds nothing
+    let nested = find_code_in(
+        &[String::from("examples"), String::from("examples/basic.rs")],
+        &mut Vec::new(),
+    )
+    .unwrap();
+    assert_eq!(names(&nested), names(&all));
+
+    // a file reached by two spellings of its path is indexed under the first root's
+    let overlapping = find_code_in(
+        &[
+            String::from("./examples/basic.rs"),
+            String::from("examples"),
+        ],
+        &mut Vec::new(),
+    )
+    .unwrap();
+    assert_eq!(overlapping.len(), all.len());
+    assert_eq!(overlapping[0].filename, "./examples/basic.rs");
+    assert!(!names(&overlapping).contains(&String::from("examples/basic.rs")));
+}
+
+#[test]
+fn test_find_code_skips_unreadable() {
+    let dir = std::env::temp_dir().join(format!("log2src-unreadable-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("good.rs"), TEST_SOURCE).unwrap();
+    fs::write(dir.join("bad.rs"), b"fn main() { debug!(\"\xff\"); }").unwrap();
+    let mut skipped = Vec::new();
+    let mut sources = find_code(dir.to_str().unwrap(), &mut skipped).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(sources.len(), 1);
+    assert!(sources[0].filename.ends_with("good.rs"));
+    assert_eq!(extract_logging(&mut sources).len(), 2);
+    assert_eq!(skipped.len(), 1);
+    assert!(skipped[0].contains("bad.rs"));
+}
+
+#[test]
+fn test_code_source_unsupported_language() {
+    for path in ["notes.txt", "Makefile"] {
+        let code = CodeSource::read(PathBuf::from(path), Box::new(TEST_SOURCE.as_bytes()));
+        assert_eq!(
+            code.err().map(|e| e.kind()),
+            Some(io::ErrorKind::InvalidInput)
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_find_code_skips_unreadable_dirs() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("log2src-locked-{}", std::process::id()));
+    let locked = dir.join("locked");
+    fs::create_dir_all(&locked).unwrap();
+    fs::write(dir.join("good.rs"), TEST_SOURCE).unwrap();
+    fs::write(locked.join("hidden.rs"), TEST_SOURCE).unwrap();
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+    // permissions don't stop a privileged user, who reads the directory like any other
+    let enforced = fs::read_dir(&locked).is_err();
+
+    let mut skipped = Vec::new();
+    let sources = find_code(dir.to_str().unwrap(), &mut skipped).unwrap();
+    let mut root_skipped = Vec::new();
+    let root_sources = find_code(locked.to_str().unwrap(), &mut root_skipped).unwrap();
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    if enforced {
+        assert_eq!(sources.len(), 1);
+        assert!(sources[0].filename.ends_with("good.rs"));
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].contains("locked"));
+        assert!(root_sources.is_empty());
+        assert_eq!(root_skipped.len(), 1);
+    } else {
+        assert_eq!(sources.len(), 2);
+        assert_eq!(root_sources.len(), 1);
+    }
+    assert!(find_code(dir.to_str().unwrap(), &mut Vec::new()).is_err());
+}
+
+#[test]
+fn test_build_matcher_escaped_braces() {
+    let matcher = build_matcher(
+        "set {{key}} = {}",
+        &SourceLanguage::Rust,
+        &ExtractOptions::default(),
+    );
     assert_eq!(
-        Regex::new(r#"(\w+)\) (\w+), (\w+)"#).unwrap().as_str(),
+        Regex::new(r#"set \{key\} = (.+)"#).unwrap().as_str(),
         matcher.as_str()
     );
+    let captures = matcher.captures("set {key} = 42").unwrap();
+    assert_eq!(captures.get(1).unwrap().as_str(), "42");
+}
+
+#[cfg(test)]
+const AUDIT_SOURCE: &str = r#"
+class Audit {
+    void login(String name) {
+        audit.info(STR."user \{name} logged in");
+    }
+}
+"#;
+
+#[test]
+fn test_extract_logging_custom_logger() {
+    let code = CodeSource::new(
+        PathBuf::from("Audit.java"),
+        Box::new(AUDIT_SOURCE.as_bytes()),
+    );
+    assert!(extract_logging(&mut [code]).is_empty());
+
+    let code = CodeSource::new(
+        PathBuf::from("Audit.java"),
+        Box::new(AUDIT_SOURCE.as_bytes()),
+    );
+    let options = ExtractOptions {
+        loggers: vec![String::from("audit")],
+        ..ExtractOptions::default()
+    };
+    let src_refs = extract_logging_with(&mut [code], &options).unwrap();
+    assert_eq!(src_refs.len(), 1);
+    assert_eq!(src_refs[0].name, "login");
+    assert_eq!(src_refs[0].vars, vec!["name"]);
+}
+
+#[test]
+fn test_extract_logging_custom_logger_is_a_name() {
+    let source = r#"
+class Audit {
+    void login(String name) {
+        audit.info("login by {}", name);
+        auditor.info("audit by {}", name);
+    }
+}
+"#;
+    for (loggers, found) in [(vec!["audit"], 1), (vec!["au.it", "a\"b\\"], 0)] {
+        let code = CodeSource::new(PathBuf::from("Audit.java"), Box::new(source.as_bytes()));
+        let options = ExtractOptions {
+            loggers: loggers.into_iter().map(String::from).collect(),
+            ..ExtractOptions::default()
+        };
+        let texts = extract_logging_with(&mut [code], &options)
+            .unwrap()
+            .into_iter()
+            .map(|src_ref| src_ref.text)
+            .collect::<Vec<_>>();
+        assert_eq!(texts.len(), found, "{:?}", texts);
+        assert!(texts.iter().all(|text| text == "\"login by {}\""));
+    }
+}
+
+#[test]
+fn test_extract_logging_builtin_logger_names() {
+    let source = r#"
+class Service {
+    void start(String name) {
+        logService.info("service {}", name);
+        errorlog.warn("error log {}", name);
+        LOGGER_AUDIT.debug("audit {}", name);
+        audit.info("not a logger {}", name);
+    }
+}
+"#;
+    // a --logger name is exact, but doesn't narrow the built-in pattern
+    for loggers in [vec![], vec![String::from("auditor")]] {
+        let code = CodeSource::new(PathBuf::from("Service.java"), Box::new(source.as_bytes()));
+        let options = ExtractOptions {
+            loggers,
+            ..ExtractOptions::default()
+        };
+        let texts = extract_logging_with(&mut [code], &options)
+            .unwrap()
+            .into_iter()
+            .map(|src_ref| src_ref.text)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            texts,
+            vec!["\"service {}\"", "\"error log {}\"", "\"audit {}\""]
+        );
+    }
+}
+
+#[test]
+fn test_grammar_info() {
+    let info = grammar_info();
+    let version = Regex::new(r"^\d+\.\d+\.\d+").unwrap();
+    assert_eq!(
+        info.iter()
+            .map(|grammar| grammar.language.as_str())
+            .collect::<Vec<&str>>(),
+        vec!["Rust", "Java", "Python", "Cpp", "Go", "Shell"]
+    );
+    for grammar in info {
+        assert!(grammar.abi_version >= tree_sitter::MIN_COMPATIBLE_LANGUAGE_VERSION);
+        assert!(grammar.abi_version <= tree_sitter::LANGUAGE_VERSION);
+        assert!(
+            version.is_match(grammar.crate_version),
+            "{}",
+            grammar.crate_version
+        );
+    }
+}
+
+#[test]
+fn test_build_matcher_adjacent_placeholders() {
+    let matcher = build_matcher("{}-{}", &SourceLanguage::Rust, &ExtractOptions::default());
+    assert_eq!(
+        Regex::new(r#"(.+?)\-(.+)"#).unwrap().as_str(),
+        matcher.as_str()
+    );
+    let captures = matcher.captures("a-b-c").unwrap();
+    assert_eq!(captures.get(1).unwrap().as_str(), "a");
+    assert_eq!(captures.get(2).unwrap().as_str(), "b-c");
+}
+
+#[test]
+fn test_build_matcher_values_with_spaces() {
+    let matcher = build_matcher(
+        "open {} failed: {}",
+        &SourceLanguage::Rust,
+        &ExtractOptions::default(),
+    );
+    // a (\w+) placeholder would stop at the first space, slash or parenthesis
+    let captures = matcher
+        .captures("open /tmp/my file.txt failed: No such file (os error 2)")
+        .unwrap();
+    assert_eq!(&captures[1], "/tmp/my file.txt");
+    assert_eq!(&captures[2], "No such file (os error 2)");
+}
+
+#[test]
+fn test_build_matcher_leading_placeholder() {
+    let options = ExtractOptions::default();
+    let matcher = build_matcher("{} done", &SourceLanguage::Rust, &options);
+    assert_eq!(matcher.as_str(), r#"(.+) done"#);
+    assert_eq!(&matcher.captures("step 3 done").unwrap()[1], "step 3");
+
+    let matcher = build_matcher("{} of {}", &SourceLanguage::Rust, &options);
+    assert_eq!(matcher.as_str(), r#"(.+?) of (.+)"#);
+    let captures = matcher.captures("page 3 of 5").unwrap();
+    assert_eq!(&captures[1], "page 3");
+    assert_eq!(&captures[2], "5");
+
+    // a message of nothing but placeholders would match every line
+    for template in ["{}", "{:?}", "{name}", "{}{}"] {
+        let matcher = build_matcher(template, &SourceLanguage::Rust, &options);
+        assert!(!matcher.is_match("step 3 done"), "{}", template);
+    }
+}
+
+#[test]
+fn test_build_matcher_format_specs() {
+    let cases = [
+        ("fill {:*>10} end", r#"fill (.+) end"#),
+        ("zero {:08.2} end", r#"zero (.+) end"#),
+        ("{:<5}|{:^7}|{:>3}", r#"(.+?)\|(.+?)\|(.+)"#),
+        ("sign {:+#x} end", r#"sign (.+) end"#),
+        ("named {name:>8} end", r#"named (.+) end"#),
+        (
+            "width {:1$} precision {:.prec$}",
+            r#"width (.+?) precision (.+)"#,
+        ),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(
+            build_matcher(text, &SourceLanguage::Rust, &ExtractOptions::default()).as_str(),
+            expected,
+            "{}",
+            text
+        );
+    }
+    let matcher = build_matcher(
+        "fill {:*>10} end",
+        &SourceLanguage::Rust,
+        &ExtractOptions::default(),
+    );
+    let captures = matcher.captures("fill *******abc end").unwrap();
+    assert_eq!(captures.get(1).unwrap().as_str(), "*******abc");
+}
+
+#[test]
+fn test_self_check() {
+    assert_eq!(self_check(), Ok(()));
+}
+
+#[test]
+fn test_parse_json_values() {
+    let source = "fn main() {\n    debug!(\"event: {} at {}\", e, t);\n}\n";
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let mut sources = vec![code];
+    let src_refs = extract_logging(&mut sources);
+    let call_graph = CallGraph::new(&mut sources);
+    let log_refs = vec![LogRef {
+        line: r#"event: {"a":1,"b":[2,3]} at 12"#,
+    }];
+    let options = MappingOptions {
+        parse_json_values: true,
+        ..MappingOptions::default()
+    };
+    let mappings = do_mappings(&log_refs, &src_refs, &call_graph, &options);
+    assert_eq!(mappings[0].variables["e"], r#"{"a":1,"b":[2,3]}"#);
+    assert_eq!(
+        mappings[0].json_values.get("e"),
+        Some(&serde_json::json!({"a": 1, "b": [2, 3]}))
+    );
+    assert_eq!(mappings[0].json_values.get("t"), None);
+
+    let mappings = do_mappings(
+        &log_refs,
+        &src_refs,
+        &call_graph,
+        &MappingOptions::default(),
+    );
+    assert!(mappings[0].json_values.is_empty());
+}
+
+#[test]
+fn test_do_mappings_truncated() {
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
+    let mut sources = vec![code];
+    let src_refs = extract_logging(&mut sources);
+    let call_graph = CallGraph::new(&mut sources);
+    let log_refs = vec![LogRef {
+        line: "[2024-02-15T03:46:44Z DEBUG nope] this won't match i=12345 …[truncated]",
+    }];
+
+    let mappings = do_mappings(
+        &log_refs,
+        &src_refs,
+        &call_graph,
+        &MappingOptions::default(),
+    );
+    assert_eq!(mappings[0].variables["i"], "12345 …[truncated]");
+    assert!(!mappings[0].truncated);
+
+    let options = MappingOptions {
+        truncation_marker: Some(Regex::new(r#" …\[truncated\]"#).unwrap()),
+        ..MappingOptions::default()
+    };
+    let mappings = do_mappings(&log_refs, &src_refs, &call_graph, &options);
+    assert!(ptr::eq(mappings[0].src_ref.unwrap(), &src_refs[1]));
+    assert_eq!(mappings[0].variables["i"], "12345");
+    assert!(mappings[0].truncated);
+}
+
+#[test]
+fn test_do_mappings_repeated() {
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
+    let mut sources = vec![code];
+    let src_refs = extract_logging(&mut sources);
+    let call_graph = CallGraph::new(&mut sources);
+    let log_refs = vec![
+        LogRef {
+            line: "Feb 15 03:46:44 host app[42]: message repeated 5 times: [ this won't match i=3]",
+        },
+        LogRef {
+            line: "Feb 15 03:46:45 host app[42]: this won't match i=4",
+        },
+    ];
+    let mappings = do_mappings(
+        &log_refs,
+        &src_refs,
+        &call_graph,
+        &MappingOptions::default(),
+    );
+    assert!(ptr::eq(mappings[0].src_ref.unwrap(), &src_refs[1]));
+    assert_eq!(mappings[0].variables["i"], "3");
+    assert_eq!(mappings[0].repeated, Some(5));
+    assert_eq!(mappings[1].variables["i"], "4");
+    assert_eq!(mappings[1].repeated, None);
+    assert_eq!(count_matches_per_file(&mappings)["in-mem.rs"], 6);
+}
+
+#[test]
+fn test_do_mappings_colored_values() {
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
+    let mut sources = vec![code];
+    let src_refs = extract_logging(&mut sources);
+    let call_graph = CallGraph::new(&mut sources);
+    let log_refs = vec![LogRef {
+        line: "[2024-02-15T03:46:44Z DEBUG nope] this won't match i=\x1b[33m12\x1b[0m",
+    }];
+    let mappings = do_mappings(
+        &log_refs,
+        &src_refs,
+        &call_graph,
+        &MappingOptions::default(),
+    );
+    assert!(ptr::eq(mappings[0].src_ref.unwrap(), &src_refs[1]));
+    assert_eq!(mappings[0].variables["i"], "12");
+}
+
+#[test]
+fn test_do_mappings_line_decoration() {
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
+    let mut sources = vec![code];
+    let src_refs = extract_logging(&mut sources);
+    let call_graph = CallGraph::new(&mut sources);
+    let log_refs = vec![LogRef {
+        line: "svc-1 | [2024-02-15T03:46:44Z DEBUG nope] this won't match i=7 <<<",
+    }];
+
+    let mappings = do_mappings(
+        &log_refs,
+        &src_refs,
+        &call_graph,
+        &MappingOptions::default(),
+    );
+    assert_eq!(mappings[0].variables["i"], "7 <<<");
+
+    let options = MappingOptions {
+        line_prefix: Some(Regex::new(r"[\w-]+ \| ").unwrap()),
+        line_suffix: Some(Regex::new(r" <<<").unwrap()),
+        ..MappingOptions::default()
+    };
+    let mappings = do_mappings(&log_refs, &src_refs, &call_graph, &options);
+    assert!(ptr::eq(mappings[0].src_ref.unwrap(), &src_refs[1]));
+    assert_eq!(mappings[0].variables["i"], "7");
+    assert_eq!(
+        strip_decoration("a | b | c", options.line_prefix.as_ref(), None),
+        "b | c"
+    );
+    assert_eq!(
+        strip_decoration("[svc-1 | x]", options.line_prefix.as_ref(), None),
+        "[svc-1 | x]"
+    );
+}
+
+#[test]
+fn test_build_matcher_normalize_timestamps() {
+    let text = "cutover at 2025-04-10T22:12:52Z for {}";
+    let line = "cutover at Apr 10, 2025 22:12:52 for 7";
+    assert!(!build_matcher(text, &SourceLanguage::Rust, &ExtractOptions::default()).is_match(line));
+
+    let options = ExtractOptions {
+        normalize_timestamps: true,
+        ..ExtractOptions::default()
+    };
+    let matcher = build_matcher(text, &SourceLanguage::Rust, &options);
+    assert_eq!(
+        matcher.as_str(),
+        format!("cutover at {} for (.+)", REFORMATTED_TIMESTAMP_REGEX)
+    );
+    let captures = matcher.captures(line).unwrap();
+    assert_eq!(captures.get(1).unwrap().as_str(), "7");
+    let captures = matcher
+        .captures("cutover at Thu Apr 10 22:12:52 UTC 2025 for 7")
+        .unwrap();
+    assert_eq!(captures.get(1).unwrap().as_str(), "7");
+    assert!(!matcher.is_match("cutover at soon for 7"));
+}
+
+#[test]
+fn test_build_matcher_normalize_trailing_timestamp() {
+    let options = ExtractOptions {
+        normalize_timestamps: true,
+        ..ExtractOptions::default()
+    };
+    let matcher = build_matcher(
+        "rotated at 2025-04-10T22:12:52Z",
+        &SourceLanguage::Rust,
+        &options,
+    );
+    assert_eq!(
+        matcher
+            .find("rotated at Apr 10, 2025 22:12:52")
+            .unwrap()
+            .as_str(),
+        "rotated at Apr 10, 2025 22:12:52"
+    );
+    assert!(matcher.is_match("rotated at 1744323172"));
+    assert!(!matcher.is_match("rotated at x"));
+    assert!(!matcher.is_match("rotated at midnight"));
+}
+
+#[test]
+fn test_find_unused() {
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
+    let mut sources = vec![code];
+    let src_refs = extract_logging(&mut sources);
+    let call_graph = CallGraph::new(&mut sources);
+    let log_refs = vec![
+        LogRef {
+            line: "[2024-02-15T03:46:44Z DEBUG nope] this won't match i=1",
+        },
+        LogRef {
+            line: "[2024-02-15T03:46:44Z DEBUG nope] this won't match i=2",
+        },
+    ];
+    let mappings = do_mappings(
+        &log_refs,
+        &src_refs,
+        &call_graph,
+        &MappingOptions::default(),
+    );
+    let unused = find_unused(&mappings, &src_refs);
+    assert_eq!(unused.len(), 1);
+    assert!(ptr::eq(unused[0], &src_refs[0]));
+}
+
+#[test]
+fn test_count_matches_per_file() {
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
+    let other = CodeSource::new(
+        PathBuf::from("other.rs"),
+        Box::new("fn other() { debug!(\"other {}\", x); }".as_bytes()),
+    );
+    let mut sources = vec![code, other];
+    let src_refs = extract_logging(&mut sources);
+    let call_graph = CallGraph::new(&mut sources);
+    let log_refs = vec![
+        LogRef {
+            line: "this won't match i=1",
+        },
+        LogRef {
+            line: "this won't match i=2",
+        },
+        LogRef { line: "other 3" },
+        LogRef { line: "nope" },
+    ];
+    let mappings = do_mappings(
+        &log_refs,
+        &src_refs,
+        &call_graph,
+        &MappingOptions::default(),
+    );
+    let counts = count_matches_per_file(&mappings);
+    assert_eq!(counts.len(), 2);
+    assert_eq!(counts["in-mem.rs"], 2);
+    assert_eq!(counts["other.rs"], 1);
+}
+
+#[test]
+fn test_map_lines() {
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
+    let mut sources = vec![code];
+    let src_refs = extract_logging(&mut sources);
+    let call_graph = CallGraph::new(&mut sources);
+    let log_refs = vec![
+        LogRef {
+            line: "you're only as funky as your last cut",
+        },
+        LogRef { line: "nope" },
+        LogRef {
+            line: "this won't match i=7",
+        },
+    ];
+    let options = MappingOptions::default();
+    let mut mappings = map_lines(&log_refs, &src_refs, &call_graph, &options);
+    let first = mappings.next().unwrap();
+    assert!(ptr::eq(first.log_ref, &log_refs[0]));
+    assert_eq!(first.src_ref.unwrap().name, "main");
+    assert!(mappings.next().unwrap().src_ref.is_none());
+    let last = mappings.next().unwrap();
+    assert_eq!(last.variables.get("i"), Some(&Cow::Borrowed("7")));
+    assert!(mappings.next().is_none());
+}
+
+#[test]
+fn test_flatten() {
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
+    let mut sources = vec![code];
+    let src_refs = extract_logging(&mut sources);
+    let call_graph = CallGraph::new(&mut sources);
+    let log_refs = vec![
+        LogRef {
+            line: "2024-05-09 19:58:53.123 [worker-1] WARN this won't match i=1",
+        },
+        LogRef { line: "nope" },
+        LogRef {
+            line: "[2024-02-15T03:46:44Z DEBUG nope] this won't match i=2",
+        },
+        LogRef {
+            line: "[INFO] [main] this won't match i=3",
+        },
+        LogRef {
+            line: "Feb 15 03:46:44 host app[42]: message repeated 5 times: [ this won't match i=4 …[cut]]",
+        },
+    ];
+    let options = MappingOptions {
+        truncation_marker: Some(Regex::new(r#" …\[cut\]"#).unwrap()),
+        ..MappingOptions::default()
+    };
+    let mappings = do_mappings(&log_refs, &src_refs, &call_graph, &options);
+    assert_eq!(
+        serde_json::to_string(&mappings[0].flatten()).unwrap(),
+        r#"{"line":"2024-05-09 19:58:53.123 [worker-1] WARN this won't match i=1","timestamp":"2024-05-09 19:58:53.123","level":"WARN","thread":"worker-1","matched":true,"confidence":0.95,"sourcePath":"in-mem.rs","lineNumber":18,"functionName":"nope","variables":"{\"i\":\"1\"}","repeated":null,"truncated":false,"indexTruncated":false}"#
+    );
+    assert_eq!(
+        serde_json::to_string(&mappings[1].flatten()).unwrap(),
+        r#"{"line":"nope","timestamp":null,"level":null,"thread":null,"matched":false,"confidence":null,"sourcePath":null,"lineNumber":null,"functionName":null,"variables":"{}","repeated":null,"truncated":false,"indexTruncated":false}"#
+    );
+
+    // a bracketed header that isn't a lone word has no thread
+    let flat = mappings[2].flatten();
+    assert_eq!(flat.timestamp, Some("2024-02-15T03:46:44Z"));
+    assert_eq!(flat.level, Some("DEBUG"));
+    assert_eq!(flat.thread, None);
+
+    // a bracketed level isn't taken for the thread
+    let flat = mappings[3].flatten();
+    assert_eq!(flat.timestamp, None);
+    assert_eq!(flat.level, Some("INFO"));
+    assert_eq!(flat.thread, Some("main"));
+
+    // nor is the pid in syslog's header, which comes before the collapsed message
+    let flat = mappings[4].flatten();
+    assert_eq!(flat.line_number, Some(18));
+    assert_eq!(flat.thread, None);
+    assert_eq!(flat.level, None);
+    assert_eq!(flat.repeated, Some(5));
+    assert!(flat.truncated);
+    assert_eq!(flat.confidence, Some(0.95));
+
+    // a line that didn't map keeps only the timestamp that starts it
+    let unmatched = vec![
+        LogRef {
+            line: "2024-05-09 19:58:53 [main] ERROR unknown",
+        },
+        LogRef {
+            line: "[2024-02-15T03:46:44Z WARN app] unknown",
+        },
+        LogRef {
+            line: "unknown [main] ERROR at 2024-05-09 19:58:53",
+        },
+    ];
+    let mappings = do_mappings(&unmatched, &src_refs, &call_graph, &options);
+    let flats = mappings.iter().map(|m| m.flatten()).collect::<Vec<_>>();
+    assert_eq!(flats[0].timestamp, Some("2024-05-09 19:58:53"));
+    assert_eq!(flats[1].timestamp, Some("2024-02-15T03:46:44Z"));
+    assert_eq!(flats[2].timestamp, None);
+    assert!(flats
+        .iter()
+        .all(|flat| flat.level.is_none() && flat.thread.is_none() && flat.confidence.is_none()));
+}
+
+#[test]
+fn test_first_hits() {
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
+    let mut sources = vec![code];
+    let src_refs = extract_logging(&mut sources);
+    let call_graph = CallGraph::new(&mut sources);
+    let log_refs = vec![
+        LogRef {
+            line: "this won't match i=1",
+        },
+        LogRef { line: "nope" },
+        LogRef {
+            line: "this won't match i=2",
+        },
+        LogRef {
+            line: "you're only as funky as your last cut",
+        },
+        LogRef {
+            line: "you're only as funky as your last cut",
+        },
+        LogRef { line: "nope" },
+    ];
+    let mappings = do_mappings(
+        &log_refs,
+        &src_refs,
+        &call_graph,
+        &MappingOptions::default(),
+    );
+    let first = first_hits(&mappings);
+    assert_eq!(first.len(), 4);
+    assert!(ptr::eq(first[0], &mappings[0]));
+    assert!(ptr::eq(first[1], &mappings[1]));
+    assert!(ptr::eq(first[2], &mappings[3]));
+    assert!(ptr::eq(first[3], &mappings[5]));
+    let hit = first[0].src_ref.unwrap();
+    assert_eq!(hit.source_path, "in-mem.rs");
+    assert_eq!(hit.line_no, 18);
+    let hit = first[2].src_ref.unwrap();
+    assert_eq!(hit.source_path, "in-mem.rs");
+    assert_eq!(hit.line_no, 7);
+    assert!(first[1].src_ref.is_none());
+    assert!(first[3].src_ref.is_none());
+}
+
+#[test]
+fn test_extract_logging_global_scope() {
+    let source = r#"
+static HANDLER: Lazy<Handler> = Lazy::new(|| {
+    debug!("installing handler");
+    Handler::default()
+});
+"#;
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs.len(), 1);
+    assert_eq!(src_refs[0].name, "<global>");
+    assert_eq!(src_refs[0].line_no, 3);
+}
+
+#[test]
+fn test_rank_candidates() {
+    let source = r#"
+fn main() {
+    debug!("{} done", step);
+    debug!("step {} done", n);
+    debug!("nothing to see");
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs.len(), 3);
+    let log_ref = LogRef {
+        line: "[2024-02-15T03:46:44Z DEBUG main] step 3 done",
+    };
+    let ranked = rank_candidates(&log_ref, &src_refs);
+    assert_eq!(ranked.len(), 2);
+    assert!(ptr::eq(ranked[0].0, &src_refs[1]));
+    assert_eq!(ranked[0].1, 10);
+    assert!(ptr::eq(ranked[1].0, &src_refs[0]));
+    // link_to_source still takes the first statement that matches
+    assert!(ptr::eq(
+        link_to_source(&log_ref, &src_refs).unwrap(),
+        &src_refs[0]
+    ));
+}
+
+#[test]
+fn test_build_matcher_windows_path() {
+    let matcher = build_matcher(
+        r#"loading config from C:\\temp\\{}.toml"#,
+        &SourceLanguage::Rust,
+        &ExtractOptions::default(),
+    );
+    assert!(matcher.is_match(r#"loading config from C:\temp\app.toml"#));
+    assert!(matcher.is_match(r#"{"msg":"loading config from C:\\temp\\app.toml"}"#));
+
+    // a backslash that doesn't start an escape is just a backslash
+    let matcher = build_matcher(
+        r#"reading D:\data\{}"#,
+        &SourceLanguage::Rust,
+        &ExtractOptions::default(),
+    );
+    assert!(matcher.is_match(r#"reading D:\data\input.csv"#));
+}
+
+#[test]
+fn test_extract_logging_concat() {
+    let source = r#"
+fn main() {
+    debug!(concat!("loaded ", "{} rows from \"", "{}\""), n, table);
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs.len(), 1);
+    assert_eq!(src_refs[0].line_no, 3);
+    assert_eq!(src_refs[0].vars, vec!["n", "table"]);
+    let log_ref = LogRef {
+        line: "[2024-02-15T03:46:44Z DEBUG main] loaded 5 rows from \"users\"",
+    };
+    let variables = extract_variables(&log_ref, &src_refs[0]);
+    assert_eq!(variables.get("n"), Some(&"5"));
+    assert_eq!(variables.get("table"), Some(&"users"));
+}
+
+#[test]
+fn test_extract_logging_generic_log() {
+    let source = r#"
+fn main() {
+    log!(target: "app", Level::Info, "loaded {} of {}", n, total);
+    debug!(target: "net", "sent {} bytes", sent);
+    log!(log::Level::Warn, "low disk");
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs.len(), 3);
+    assert_eq!(src_refs[0].text, "\"loaded {} of {}\"");
+    assert_eq!(src_refs[0].vars, vec!["n", "total"]);
+    assert_eq!(src_refs[0].level.as_deref(), Some("Info"));
+    assert_eq!(src_refs[1].text, "\"sent {} bytes\"");
+    assert_eq!(src_refs[1].vars, vec!["sent"]);
+    assert_eq!(src_refs[1].level.as_deref(), Some("debug"));
+    assert_eq!(src_refs[2].text, "\"low disk\"");
+    assert!(src_refs[2].vars.is_empty());
+    assert_eq!(src_refs[2].level.as_deref(), Some("Warn"));
+}
+
+#[test]
+fn test_extract_logging_emit_pattern() {
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs[1].pattern, None);
+
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
+    let options = ExtractOptions {
+        emit_pattern: true,
+        ..ExtractOptions::default()
+    };
+    let src_refs = extract_logging_with(&mut [code], &options).unwrap();
+    assert_eq!(
+        src_refs[1].pattern.as_deref(),
+        Some("this won't match i=(.+)")
+    );
+}
+
+#[test]
+fn test_extract_logging_format_args() {
+    let source = r#"
+fn handle(id: u64) {
+    log_internal(Level::Warn, format_args!("slow request id={}", id));
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs.len(), 1);
+    assert_eq!(src_refs[0].text, "\"slow request id={}\"");
+    assert_eq!(src_refs[0].name, "handle");
+    assert_eq!(src_refs[0].vars, vec!["id"]);
+    let log_ref = LogRef {
+        line: "WARN slow request id=42",
+    };
+    assert_eq!(extract_variables(&log_ref, &src_refs[0])["id"], "42");
+}
+
+#[test]
+fn test_extract_logging_tracing_fields() {
+    let source = r#"
+fn login(uid: u64, name: &str, elapsed: u32) {
+    tracing::event!(Level::INFO, user_id = %uid, attempt = ?n, "login ok for {} in {}ms", name, elapsed);
+    info!(target: "auth", %uid, "login failed");
+    debug!("no fields {}", name);
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs.len(), 3);
+    let event = &src_refs[0];
+    assert_eq!(event.line_no, 3);
+    assert_eq!(event.column, 63);
+    assert_eq!(event.name, "login");
+    assert_eq!(event.text, "\"login ok for {} in {}ms\"");
+    assert_eq!(event.level.as_deref(), Some("INFO"));
+    assert_eq!(event.vars, vec!["name", "elapsed", "user_id", "attempt"]);
+    let log_ref = LogRef {
+        line: "2024-05-09T19:58:53Z  INFO app: login ok for bob in 12ms user_id=123 attempt=2",
+    };
+    assert!(ptr::eq(link_to_source(&log_ref, &src_refs).unwrap(), event));
+    let variables = extract_variables(&log_ref, event);
+    assert_eq!(variables["name"], "bob");
+    assert_eq!(variables["elapsed"], "12");
+    assert_eq!(variables["user_id"], "123");
+    assert_eq!(variables["attempt"], "2");
+
+    assert_eq!(src_refs[1].text, "\"login failed\"");
+    assert_eq!(src_refs[1].vars, vec!["uid"]);
+    let log_ref = LogRef {
+        line: "WARN auth: login failed uid=7",
+    };
+    assert_eq!(extract_variables(&log_ref, &src_refs[1])["uid"], "7");
+    assert_eq!(src_refs[2].text, "\"no fields {}\"");
+    assert_eq!(src_refs[2].vars, vec!["name"]);
+}
+
+#[test]
+fn test_extract_logging_tracing_by_path() {
+    let source = r#"
+fn login(name: &str) {
+    tracing::info!("plain msg {}", name);
+    tracing::warn!(target: "auth", "denied {}", name);
+    tracing::event!(Level::ERROR, "failed for {}", name);
+    info!("unqualified {}", name);
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(
+        src_refs
+            .iter()
+            .map(|src_ref| (src_ref.line_no, src_ref.text.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            (3, "\"plain msg {}\""),
+            (4, "\"denied {}\""),
+            (5, "\"failed for {}\""),
+            (6, "\"unqualified {}\"")
+        ]
+    );
+    assert_eq!(src_refs[0].vars, vec!["name"]);
+    assert_eq!(src_refs[0].level.as_deref(), Some("info"));
+    assert_eq!(src_refs[1].level.as_deref(), Some("warn"));
+    assert_eq!(src_refs[2].level.as_deref(), Some("ERROR"));
+    let log_ref = LogRef {
+        line: "2024-05-09T19:58:53Z  INFO app: plain msg bob",
+    };
+    assert!(ptr::eq(
+        link_to_source(&log_ref, &src_refs).unwrap(),
+        &src_refs[0]
+    ));
+    assert_eq!(extract_variables(&log_ref, &src_refs[0])["name"], "bob");
+}
+
+#[test]
+fn test_extract_logging_key_values() {
+    let source = r#"
+fn serve(addr: &str, tries: u32) {
+    debug!(target: "net", peer = addr, tries:? = tries; "connected to {} after {}", addr, tries);
+    debug!(peer = addr; "closed");
+    info!(target: "net", key = value; "message {}", x);
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs.len(), 3);
+    assert_eq!(src_refs[0].text, "\"connected to {} after {}\"");
+    assert_eq!(src_refs[0].vars, vec!["addr", "tries", "peer", "tries"]);
+    assert_eq!(src_refs[1].text, "\"closed\"");
+    assert_eq!(src_refs[1].vars, vec!["peer"]);
+    assert_eq!(src_refs[2].text, "\"message {}\"");
+    assert_eq!(src_refs[2].vars, vec!["x", "key"]);
+    assert_eq!(src_refs[2].level.as_deref(), Some("info"));
+    let log_ref = LogRef {
+        line: "connected to 10.0.0.1 after 3",
+    };
+    let variables = extract_variables(&log_ref, &src_refs[0]);
+    assert_eq!(variables["addr"], "10.0.0.1");
+    assert_eq!(variables["tries"], "3");
+}
+
+#[test]
+fn test_extract_logging_emit_placeholder_offsets() {
+    let source = r#"
+fn main() {
+    debug!("set {{key}} = {} for {name}", value);
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs[0].placeholder_offsets, None);
+
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let options = ExtractOptions {
+        emit_placeholder_offsets: true,
+        ..ExtractOptions::default()
+    };
+    let src_refs = extract_logging_with(&mut [code], &options).unwrap();
+    let offsets = src_refs[0].placeholder_offsets.as_ref().unwrap();
+    assert_eq!(offsets, &vec![15, 22]);
+    assert_eq!(&src_refs[0].text[15..17], "{}");
+    assert_eq!(&src_refs[0].text[22..28], "{name}");
+}
+
+#[test]
+fn test_extract_logging_emit_lsp_positions() {
+    let source = "fn main() {\n    /* 😀é */ debug!(\"hi {}\", x);\n}\n";
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs[0].position, None);
+
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let options = ExtractOptions {
+        emit_lsp_positions: true,
+        ..ExtractOptions::default()
+    };
+    let src_refs = extract_logging_with(&mut [code], &options).unwrap();
+    assert_eq!(src_refs[0].line_no, 2);
+    assert_eq!(src_refs[0].column, 24);
+    assert_eq!(
+        src_refs[0].position,
+        Some(LspPosition {
+            line: 1,
+            character: 21
+        })
+    );
+}
+
+#[test]
+fn test_extract_logging_emit_capture() {
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs[1].capture, None);
+
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
+    let options = ExtractOptions {
+        emit_capture: true,
+        ..ExtractOptions::default()
+    };
+    let src_refs = extract_logging_with(&mut [code], &options).unwrap();
+    assert_eq!(src_refs[1].capture.as_deref(), Some("log"));
+
+    let source = r#"
+class Login {
+    void login(String user) {
+        logger.info(messages.getString("user.login"), user);
+    }
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("Login.java"), Box::new(source.as_bytes()));
+    let options = ExtractOptions {
+        emit_capture: true,
+        message_bundle: parse_message_bundle("user.login=User {} logged in"),
+        ..ExtractOptions::default()
+    };
+    let src_refs = extract_logging_with(&mut [code], &options).unwrap();
+    assert_eq!(src_refs[0].capture.as_deref(), Some("bundle-key"));
+}
+
+#[test]
+fn test_build_matcher_lazy_placeholders() {
+    let line = "path x end end";
+    let greedy = build_matcher(
+        "path {} end",
+        &SourceLanguage::Rust,
+        &ExtractOptions::default(),
+    );
+    assert_eq!(&greedy.captures(line).unwrap()[1], "x end");
+
+    let options = ExtractOptions {
+        lazy_placeholders: true,
+        ..ExtractOptions::default()
+    };
+    let lazy = build_matcher("path {} end", &SourceLanguage::Rust, &options);
+    assert_eq!(&lazy.captures(line).unwrap()[1], "x");
+
+    // a trailing placeholder still takes the rest of the line
+    let lazy = build_matcher("path {}", &SourceLanguage::Rust, &options);
+    assert_eq!(&lazy.captures("path x end").unwrap()[1], "x end");
+}
+
+#[test]
+fn test_extract_logging_spans() {
+    let source = r#"
+fn handle(id: u32, path: &str) {
+    let span = info_span!("handle_request", id);
+    let _enter = span.enter();
+    let load = span!(Level::DEBUG, "load_config", path);
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs.len(), 2);
+    assert_eq!(src_refs[0].text, "\"handle_request\"");
+    assert_eq!(src_refs[0].name, "handle");
+    assert_eq!(src_refs[0].vars, vec!["id"]);
+    assert_eq!(src_refs[1].text, "\"load_config\"");
+    assert_eq!(src_refs[1].vars, vec!["path"]);
+    assert_eq!(src_refs[1].level.as_deref(), Some("DEBUG"));
+
+    let log_ref = LogRef {
+        line: "2024-02-15T03:46:44Z  INFO handle_request{id=3}: app: new",
+    };
+    let result = link_to_source(&log_ref, &src_refs);
+    assert!(ptr::eq(result.unwrap(), &src_refs[0]));
+}
+
+#[test]
+fn test_do_mappings_normalize_values() {
+    let source = "fn main() {\n    debug!(\"request {} took {}ms\", id, ms);\n}\n";
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let mut sources = vec![code];
+    let src_refs = extract_logging(&mut sources);
+    let call_graph = CallGraph::new(&mut sources);
+    let log_refs = vec![LogRef {
+        line: "request 1b4e28ba-2fa1-11d2-883f-0016d3cca427 took 12ms",
+    }];
+    let options = MappingOptions {
+        normalize_values: vec![
+            "[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}=<uuid>"
+                .parse()
+                .unwrap(),
+        ],
+        ..MappingOptions::default()
+    };
+    let mappings = do_mappings(&log_refs, &src_refs, &call_graph, &options);
+    assert_eq!(mappings[0].variables["id"], "<uuid>");
+    assert!(matches!(mappings[0].variables["ms"], Cow::Borrowed("12")));
+
+    assert!("no-equals".parse::<ValueNormalizer>().is_err());
+    assert!("(=x".parse::<ValueNormalizer>().is_err());
+}
+
+#[test]
+fn test_extract_logging_error_context() {
+    let source = r#"
+fn load(path: &str) -> anyhow::Result<Config> {
+    let text = fs::read_to_string(path).context("loading config")?;
+    toml::from_str(&text).with_context(|| format!("parsing {}", path))
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    assert!(extract_logging(&mut [code]).is_empty());
+
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let options = ExtractOptions {
+        include_error_context: true,
+        ..ExtractOptions::default()
+    };
+    let src_refs = extract_logging_with(&mut [code], &options).unwrap();
+    assert_eq!(src_refs.len(), 2);
+    assert_eq!(src_refs[0].text, "\"loading config\"");
+    assert_eq!(src_refs[0].name, "load");
+    assert_eq!(src_refs[1].text, "\"parsing {}\"");
+    assert_eq!(src_refs[1].vars, vec!["path"]);
+
+    let log_ref = LogRef {
+        line: "[2024-02-15T03:46:44Z ERROR app] failed to start: loading config",
+    };
+    assert!(ptr::eq(
+        link_to_source(&log_ref, &src_refs).unwrap(),
+        &src_refs[0]
+    ));
+}
+
+#[test]
+fn test_build_matcher_literal_delimiters() {
+    let options = ExtractOptions::default();
+    let matcher = build_matcher("100%% done {}", &SourceLanguage::Rust, &options);
+    assert_eq!(matcher.captures_len(), 2);
+    assert_eq!(&matcher.captures("100%% done now").unwrap()[1], "now");
+
+    let matcher = build_matcher("{{literal}} 100% done {}", &SourceLanguage::Rust, &options);
+    assert_eq!(matcher.captures_len(), 2);
+    assert_eq!(
+        &matcher.captures("{literal} 100% done now").unwrap()[1],
+        "now"
+    );
+
+    let matcher = build_matcher("100% done {}", &SourceLanguage::Java, &options);
+    assert_eq!(matcher.captures_len(), 2);
+    assert_eq!(&matcher.captures("100% done now").unwrap()[1], "now");
+}
+
+#[test]
+fn test_build_matcher_delimiter_collisions() {
+    let options = ExtractOptions::default();
+    // (template, line, values): a value holding the literal around its placeholder still
+    // splits where the template says, since the placeholders before the last only stop at a
+    // place the rest of the template can match from
+    let cases = [
+        ("[{}]", "[[nested]]", vec!["[nested]"]),
+        ("[{}] {}", "[[a]] done", vec!["[a]", "done"]),
+        ("id=[{}] state={}", "id=[[x]] state=ok", vec!["[x]", "ok"]),
+        ("a={} b={}", "a=x b=y b=z", vec!["x", "y b=z"]),
+        ("a={} b={}", "a=x=1 b=y", vec!["x=1", "y"]),
+        ("{}: {}", "host:8080: refused", vec!["host:8080", "refused"]),
+        ("said \"{}\"", "said \"hi \"there\"\"", vec!["hi \"there\""]),
+    ];
+    // when a value before the last holds the literal that follows it, the line reads the same
+    // for more than one split, so it splits at the first place that matches
+    let ambiguous = [
+        // a="x b=w", b="y"
+        ("a={} b={}", "a=x b=w b=y", vec!["x", "w b=y"]),
+        // a="(1, 2)", b="3"
+        ("({}, {})", "((1, 2), 3)", vec!["(1", "2), 3"]),
+        // a="x] [y", b="done"
+        ("[{}] [{}]", "[x] [y] [done]", vec!["x", "y] [done"]),
+    ];
+    for (template, line, values) in cases.into_iter().chain(ambiguous) {
+        let matcher = build_matcher(template, &SourceLanguage::Rust, &options);
+        let captures = matcher.captures(line).unwrap();
+        let captured = captures
+            .iter()
+            .skip(1)
+            .map(|m| m.unwrap().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(captured, values, "{} against {}", template, line);
+    }
+}
+
+#[test]
+fn test_find_callers() {
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
+    let mut sources = vec![code];
+    let src_refs = extract_logging(&mut sources);
+    let call_graph = CallGraph::new(&mut sources);
+
+    let callers = find_callers(&src_refs[1], &call_graph);
+    assert_eq!(callers.len(), 1);
+    assert_eq!(callers[0].name, "foo");
+    assert_eq!(callers[0].text, "nope");
+    assert_eq!(callers[0].line_no, 14);
+
+    assert!(find_callers(&src_refs[0], &call_graph).is_empty());
+}
+
+#[test]
+fn test_extract_logging_skips_comments() {
+    let source = r#"
+fn main() {
+    // debug!("old message {}", x);
+    debug!("new message {}", /* was x */ y);
+    /* debug!("older message {}", z); */
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs.len(), 1);
+    assert_eq!(src_refs[0].text, "\"new message {}\"");
+    assert_eq!(src_refs[0].vars, vec!["y"]);
+}
+
+#[test]
+fn test_rank_candidates_with_weights() {
+    let source = r#"
+fn main() {
+    debug!("{} done", step);
+    debug!("step {} done", n);
+    debug!("step {} {}", n, state);
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    let log_ref = LogRef {
+        line: "step 3 done",
+    };
+    let scores = |weights: &QualityWeights| {
+        rank_candidates_with(&log_ref, &src_refs, weights)
+            .into_iter()
+            .map(|(src_ref, score)| (src_ref.line_no, score))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        scores(&QualityWeights::default()),
+        vec![(4, 10), (5, 6), (3, 5)]
+    );
+    let segments = QualityWeights {
+        literal: 0,
+        placeholders: 0,
+        segments: 1,
+    };
+    assert_eq!(scores(&segments), vec![(4, 2), (5, 2), (3, 1)]);
+    let fewer_placeholders = QualityWeights {
+        placeholders: -10,
+        ..QualityWeights::default()
+    };
+    assert_eq!(scores(&fewer_placeholders), vec![(4, 0), (3, -5), (5, -14)]);
+}
+
+#[test]
+fn test_extract_logging_max_statements() {
+    let mut sources = vec![
+        CodeSource::new(PathBuf::from("a.rs"), Box::new(TEST_SOURCE.as_bytes())),
+        CodeSource::new(PathBuf::from("b.rs"), Box::new(TEST_SOURCE.as_bytes())),
+        CodeSource::new(PathBuf::from("c.rs"), Box::new(TEST_SOURCE.as_bytes())),
+    ];
+    let options = ExtractOptions {
+        max_statements: Some(3),
+        ..ExtractOptions::default()
+    };
+    let (src_refs, truncated) = extract_logging_capped(&mut sources, &options).unwrap();
+    assert!(truncated);
+    assert_eq!(
+        src_refs
+            .iter()
+            .map(|src_ref| (src_ref.source_path.as_str(), src_ref.line_no))
+            .collect::<Vec<_>>(),
+        vec![("a.rs", 7), ("a.rs", 18), ("b.rs", 7)]
+    );
+
+    let log_ref = LogRef {
+        line: "[2024-02-15T03:46:44Z DEBUG nope] this won't match i=1",
+    };
+    assert!(ptr::eq(
+        link_to_source(&log_ref, &src_refs).unwrap(),
+        &src_refs[1]
+    ));
+    let mapping_options = MappingOptions {
+        index_truncated: truncated,
+        ..MappingOptions::default()
+    };
+    let call_graph = CallGraph::new(&mut sources);
+    let log_refs = [log_ref];
+    let mappings = do_mappings(&log_refs, &src_refs, &call_graph, &mapping_options);
+    assert!(mappings[0].index_truncated);
+    assert!(mappings[0].flatten().index_truncated);
+
+    // a cap the tree fits under, even exactly, leaves the index whole
+    let options = ExtractOptions {
+        max_statements: Some(6),
+        ..ExtractOptions::default()
+    };
+    let (src_refs, truncated) = extract_logging_capped(&mut sources, &options).unwrap();
+    assert_eq!(src_refs.len(), 6);
+    assert!(!truncated);
+
+    // files past the cap without any statements don't make it a truncated index
+    let mut sources = vec![
+        CodeSource::new(PathBuf::from("a.rs"), Box::new(TEST_SOURCE.as_bytes())),
+        CodeSource::new(PathBuf::from("b.rs"), Box::new("fn main() {}\n".as_bytes())),
+        CodeSource::new(PathBuf::from("c.rs"), Box::new("fn main() {}\n".as_bytes())),
+    ];
+    let options = ExtractOptions {
+        max_statements: Some(2),
+        ..ExtractOptions::default()
+    };
+    let (src_refs, truncated) = extract_logging_capped(&mut sources, &options).unwrap();
+    assert_eq!(src_refs.len(), 2);
+    assert!(!truncated);
+}
+
+#[test]
+fn test_do_mappings_max_candidates() {
+    let mut sources = vec![
+        CodeSource::new(PathBuf::from("a.rs"), Box::new(TEST_SOURCE.as_bytes())),
+        CodeSource::new(PathBuf::from("b.rs"), Box::new(TEST_SOURCE.as_bytes())),
+    ];
+    let mut src_refs = extract_logging(&mut sources);
+    let call_graph = CallGraph::new(&mut sources);
+    let log_refs = [LogRef {
+        line: "[2024-02-15T03:46:44Z DEBUG nope] this won't match i=1",
+    }];
+    // the cap applies after filtering, so it can't drop the statements the filter kept
+    filter_source_refs(&mut src_refs, &"b.rs".parse().unwrap());
+    let mapping_options = MappingOptions {
+        max_candidates: Some(2),
+        ..MappingOptions::default()
+    };
+    let mappings = do_mappings(&log_refs, &src_refs, &call_graph, &mapping_options);
+    assert_eq!(
+        mappings[0]
+            .src_ref
+            .map(|src_ref| src_ref.source_path.as_str()),
+        Some("b.rs")
+    );
+    assert!(!mappings[0].index_truncated);
+
+    let mapping_options = MappingOptions {
+        max_candidates: Some(1),
+        ..MappingOptions::default()
+    };
+    let mappings = do_mappings(&log_refs, &src_refs, &call_graph, &mapping_options);
+    assert_eq!(mappings[0].src_ref, None);
+    assert!(mappings[0].index_truncated);
+}
+
+#[test]
+fn test_extract_logging_level_macros() {
+    let source = r#"
+fn serve(port: u16, e: Error) {
+    trace!("polling {}", port);
+    debug!("accepted on {}", port);
+    info!("started {}", port);
+    warn!(target: "net", "slow {}", port);
+    error!("failed {}", e);
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(
+        src_refs
+            .iter()
+            .map(|src_ref| (src_ref.text.as_str(), src_ref.level.as_deref()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("\"polling {}\"", Some("trace")),
+            ("\"accepted on {}\"", Some("debug")),
+            ("\"started {}\"", Some("info")),
+            ("\"slow {}\"", Some("warn")),
+            ("\"failed {}\"", Some("error")),
+        ]
+    );
+    assert_eq!(src_refs[0].vars, vec!["port"]);
+    assert_eq!(src_refs[4].vars, vec!["e"]);
+    let log_ref = LogRef {
+        line: "[2024-02-15T03:46:44Z ERROR app] failed connection reset",
+    };
+    assert!(ptr::eq(
+        link_to_source(&log_ref, &src_refs).unwrap(),
+        &src_refs[4]
+    ));
+}
+
+#[test]
+fn test_rank_candidates_level() {
+    let source = r#"
+fn check(n: u32) {
+    debug!("disk at {}%", n);
+    warn!("disk at {}%", n);
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs.len(), 2);
+    assert_eq!(src_refs[0].level.as_deref(), Some("debug"));
+    assert_eq!(src_refs[1].level.as_deref(), Some("warn"));
+    let warn = LogRef {
+        line: "[2024-02-15T03:46:44Z WARN app] disk at 95%",
+    };
+    assert!(ptr::eq(
+        rank_candidates(&warn, &src_refs)[0].0,
+        &src_refs[1]
+    ));
+    let debug = LogRef {
+        line: "[2024-02-15T03:46:44Z DEBUG app] disk at 95%",
+    };
+    assert!(ptr::eq(
+        rank_candidates(&debug, &src_refs)[0].0,
+        &src_refs[0]
+    ));
+    // without a level in the line it falls back to the source order
+    let bare = LogRef {
+        line: "disk at 95%",
+    };
+    assert!(ptr::eq(
+        rank_candidates(&bare, &src_refs)[0].0,
+        &src_refs[0]
+    ));
+}
+
+#[test]
+fn test_rank_candidates_tie_break() {
+    let mut sources = vec![
+        CodeSource::new(PathBuf::from("b.rs"), Box::new(TEST_SOURCE.as_bytes())),
+        CodeSource::new(PathBuf::from("a.rs"), Box::new(TEST_SOURCE.as_bytes())),
+    ];
+    let src_refs = extract_logging(&mut sources);
+    assert_eq!(src_refs[0].source_path, "b.rs");
+    let log_ref = LogRef {
+        line: "[2024-02-15T03:46:44Z DEBUG nope] this won't match i=1",
+    };
+    let ranked = rank_candidates(&log_ref, &src_refs);
+    assert_eq!(ranked.len(), 2);
+    assert_eq!(ranked[0].1, ranked[1].1);
+    assert_eq!(ranked[0].0.source_path, "a.rs");
+    // unlike link_to_source, which goes by the order of `src_refs`
+    assert_eq!(
+        link_to_source(&log_ref, &src_refs).unwrap().source_path,
+        "b.rs"
+    );
+}
+
+#[test]
+fn test_extract_logging_message_bundle() {
+    let bundle = parse_message_bundle(
+        "# login messages\nuser.login = User {} logged in after {} attempts\n\nuser.unknown: Unknown user\n",
+    );
+    assert_eq!(bundle.len(), 2);
+    assert_eq!(bundle["user.unknown"], "Unknown user");
+
+    let source = r#"
+class Login {
+    void login(String user, int attempts) {
+        logger.info(messages.getString("user.login"), user, attempts);
+        logger.warn(messages.getString("user.missing"));
+    }
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("Login.java"), Box::new(source.as_bytes()));
+    assert!(extract_logging(&mut [code]).is_empty());
+
+    let code = CodeSource::new(PathBuf::from("Login.java"), Box::new(source.as_bytes()));
+    let options = ExtractOptions {
+        message_bundle: bundle,
+        ..ExtractOptions::default()
+    };
+    let src_refs = extract_logging_with(&mut [code], &options).unwrap();
+    assert_eq!(src_refs.len(), 2);
+    assert_eq!(src_refs[0].text, "\"user.login\"");
+    assert_eq!(src_refs[0].vars, vec!["user", "attempts"]);
+    let log_ref = LogRef {
+        line: "INFO Login: User alice logged in after 3 attempts",
+    };
+    assert!(ptr::eq(
+        link_to_source(&log_ref, &src_refs).unwrap(),
+        &src_refs[0]
+    ));
+    let variables = extract_variables(&log_ref, &src_refs[0]);
+    assert_eq!(variables["user"], "alice");
+    assert_eq!(variables["attempts"], "3");
+}
+
+#[test]
+fn test_do_mappings_prebuilt_log_refs() {
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
+    let mut sources = vec![code];
+    let src_refs = extract_logging(&mut sources);
+    let call_graph = CallGraph::new(&mut sources);
+    let lines = [
+        String::from("[2024-02-15T03:46:44Z DEBUG nope] this won't match i=1"),
+        String::from("[2024-02-15T03:46:44Z DEBUG nope] this won't match i=2"),
+        String::from("not a log line"),
+    ];
+    let log_refs = lines
+        .iter()
+        .map(|line| LogRef { line })
+        .collect::<Vec<LogRef>>();
+    let mappings = do_mappings(
+        &log_refs[1..],
+        &src_refs,
+        &call_graph,
+        &MappingOptions::default(),
+    );
+    assert_eq!(mappings.len(), 2);
+    assert!(ptr::eq(mappings[0].log_ref, &log_refs[1]));
+    assert_eq!(mappings[0].src_ref.map(|src_ref| src_ref.line_no), Some(18));
+    assert_eq!(mappings[0].variables["i"], "2");
+    assert_eq!(mappings[1].src_ref, None);
+}
+
+#[test]
+fn test_do_mappings_in_parallel() {
+    let code = CodeSource::new(PathBuf::from("in-mem.rs"), Box::new(TEST_SOURCE.as_bytes()));
+    let mut sources = vec![code];
+    let src_refs = extract_logging(&mut sources);
+    let call_graph = CallGraph::new(&mut sources);
+    // enough lines to be split among threads, in an order each mapping has to keep
+    let lines = (0..MIN_LINES_PER_THREAD * 4)
+        .map(|i| match i % 3 {
+            0 => String::from("not a log line"),
+            _ => format!("[2024-02-15T03:46:44Z DEBUG nope] this won't match i={}", i),
+        })
+        .collect::<Vec<String>>();
+    let log_refs = lines
+        .iter()
+        .map(|line| LogRef { line })
+        .collect::<Vec<LogRef>>();
+    let options = MappingOptions::default();
+    let mappings = do_mappings(&log_refs, &src_refs, &call_graph, &options);
+    assert_eq!(mappings.len(), lines.len());
+    for (i, mapping) in mappings.iter().enumerate() {
+        assert!(ptr::eq(mapping.log_ref, &log_refs[i]));
+        match i % 3 {
+            0 => assert_eq!(mapping.src_ref, None),
+            _ => assert_eq!(mapping.variables["i"], i.to_string()),
+        }
+    }
+}
+
+#[test]
+fn test_extract_logging_python() {
+    let source = r#"
+class Deployer:
+    def run(self, user, action, host):
+        logger.info("user {} did {}".format(user, action))
+        self.log.warning(f"retrying {host!r} for {user}")
+        logging.error("deploy of %s failed: %d%%", host, code)
+        logger.debug("{who} on {where}".format(who=user, where=host))
+"#;
+    let code = CodeSource::new(PathBuf::from("deploy.py"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs.len(), 4);
+    assert_eq!(src_refs[0].text, r#""user {} did {}""#);
+    assert_eq!(src_refs[0].name, "run");
+    assert_eq!(src_refs[0].vars, vec!["user", "action"]);
+    assert_eq!(src_refs[1].vars, vec!["host", "user"]);
+    assert_eq!(src_refs[2].vars, vec!["host", "code"]);
+    assert_eq!(src_refs[3].vars, vec!["who", "where"]);
+
+    let log_ref = LogRef {
+        line: "2024-05-09 19:58:53,117 INFO deploy: user alice did rollback",
+    };
+    let variables = extract_variables(&log_ref, &src_refs[0]);
+    assert_eq!(variables["user"], "alice");
+    assert_eq!(variables["action"], "rollback");
+    let log_ref = LogRef {
+        line: "ERROR:root:deploy of web-1 failed: 42%",
+    };
+    assert!(ptr::eq(
+        link_to_source(&log_ref, &src_refs).unwrap(),
+        &src_refs[2]
+    ));
+    assert_eq!(extract_variables(&log_ref, &src_refs[2])["code"], "42");
+}
+
+#[test]
+fn test_extract_logging_cpp_stream() {
+    let source = r#"
+void Server::run(int id) {
+    LOG(INFO) << "user " << id << " done in " << timer.elapsed() << "ms";
+    LOG_IF(WARNING, full) << "100% of " << capacity << " {slots} used" << std::endl;
+    spdlog::error("lost {} of {}", n, total);
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("server.cc"), Box::new(source.as_bytes()));
+    let options = ExtractOptions {
+        emit_placeholder_offsets: true,
+        ..ExtractOptions::default()
+    };
+    let src_refs = extract_logging_with(&mut [code], &options).unwrap();
+    assert_eq!(src_refs.len(), 3);
+    assert_eq!(src_refs[0].name, "Server::run");
+    assert_eq!(src_refs[0].vars, vec!["id", "timer.elapsed()"]);
+    assert_eq!(src_refs[0].level.as_deref(), Some("INFO"));
+    assert_eq!(src_refs[0].placeholder_offsets, Some(vec![24, 45]));
+    assert_eq!(src_refs[1].vars, vec!["capacity"]);
+    assert_eq!(src_refs[2].vars, vec!["n", "total"]);
+    assert_eq!(src_refs[2].level.as_deref(), Some("error"));
+
+    let log_ref = LogRef {
+        line: "I20240509 19:58:53.117000  1234 server.cc:3] user 42 done in 17ms",
+    };
+    let variables = extract_variables(&log_ref, &src_refs[0]);
+    assert_eq!(variables["id"], "42");
+    assert_eq!(variables["timer.elapsed()"], "17");
+    let log_ref = LogRef {
+        line: "W20240509 19:58:53.117000  1234 server.cc:4] 100% of 8 {slots} used",
+    };
+    assert!(ptr::eq(
+        link_to_source(&log_ref, &src_refs).unwrap(),
+        &src_refs[1]
+    ));
+    assert_eq!(extract_variables(&log_ref, &src_refs[1])["capacity"], "8");
+}
+
+#[test]
+fn test_extract_logging_cpp_local_binding() {
+    let source = r#"
+void Server::accept(int id, const std::string& peer) {
+    auto s = fmt::format("accepted {} from {}", id, peer);
+    logger->info(s);
+    std::string t = fmt::format("unused {}", id);
+    auto u = s;
+    logger->warn(u);
+    logger->error(missing);
+}
+
+void Server::close() {
+    logger->info(s);
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("server.cc"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    // only the template that's logged is a statement, and a binding is resolved in the
+    // function that uses it, one level deep
+    assert_eq!(src_refs.len(), 1);
+    assert_eq!(src_refs[0].text, r#""accepted {} from {}""#);
+    assert_eq!(src_refs[0].line_no, 3);
+    assert_eq!(src_refs[0].name, "Server::accept");
+    assert_eq!(src_refs[0].vars, vec!["id", "peer"]);
+    assert_eq!(src_refs[0].level.as_deref(), Some("info"));
+
+    let log_ref = LogRef {
+        line: "[2024-05-09 19:58:53.117] [info] accepted 7 from 10.0.0.2",
+    };
+    let variables = extract_variables(&log_ref, &src_refs[0]);
+    assert_eq!(variables["id"], "7");
+    assert_eq!(variables["peer"], "10.0.0.2");
+}
+
+#[test]
+fn test_extract_logging_go() {
+    let source = r#"package main
+
+func (s *Server) greet(name string, age int) {
+	log.Printf("user %s age %d", name, age)
+	logrus.Warnf(`slow %+v`, s)
+	logger.Info("greeted", zap.String("user", name))
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("server.go"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs.len(), 3);
+    assert_eq!(src_refs[0].name, "greet");
+    assert_eq!(src_refs[0].vars, vec!["name", "age"]);
+    assert_eq!(src_refs[1].text, "`slow %+v`");
+    assert_eq!(src_refs[1].vars, vec!["s"]);
+    assert!(src_refs[2].vars.is_empty());
+
+    let log_ref = LogRef {
+        line: "2024/05/09 19:58:53 user alice age 42",
+    };
+    let variables = extract_variables(&log_ref, &src_refs[0]);
+    assert_eq!(variables["name"], "alice");
+    assert_eq!(variables["age"], "42");
+    let log_ref = LogRef {
+        line: r#"{"level":"info","ts":1715284733.117,"msg":"greeted","user":"alice"}"#,
+    };
+    assert!(ptr::eq(
+        link_to_source(&log_ref, &src_refs).unwrap(),
+        &src_refs[2]
+    ));
+}
+
+#[test]
+fn test_extract_logging_shell() {
+    let source = r#"#!/bin/bash
+deploy() {
+    echo "deploying $app to ${host:-localhost}"
+    printf "took %d seconds\n" "$secs"
+    echo -n 'no newline'
+    logger -t deploy "deployed $app"
+    echo done
+}
+"#;
+    let code = CodeSource::new(PathBuf::from("deploy.sh"), Box::new(source.as_bytes()));
+    let src_refs = extract_logging(&mut [code]);
+    assert_eq!(src_refs.len(), 4);
+    assert_eq!(src_refs[0].name, "deploy");
+    assert_eq!(src_refs[0].vars, vec!["app", "host"]);
+    assert_eq!(src_refs[1].vars, vec!["secs"]);
+    assert_eq!(src_refs[2].text, "'no newline'");
+    assert_eq!(src_refs[3].line_no, 6);
+    assert_eq!(src_refs[3].vars, vec!["app"]);
+
+    let log_ref = LogRef {
+        line: "deploying web to prod-1",
+    };
+    let variables = extract_variables(&log_ref, &src_refs[0]);
+    assert_eq!(variables["app"], "web");
+    assert_eq!(variables["host"], "prod-1");
+    let log_ref = LogRef {
+        line: "took 12 seconds",
+    };
+    assert_eq!(extract_variables(&log_ref, &src_refs[1])["secs"], "12");
+}
+
+#[test]
+fn test_from_shebang() {
+    let cases = [
+        ("#!/usr/bin/env python3", Some(SourceLanguage::Python)),
+        ("#!/usr/bin/python3.12 -u", Some(SourceLanguage::Python)),
+        ("#!/usr/bin/env -S python -u", Some(SourceLanguage::Python)),
+        ("#!/bin/bash -e", Some(SourceLanguage::Shell)),
+        ("#! /bin/sh", Some(SourceLanguage::Shell)),
+        ("#!/usr/bin/env node", None),
+        ("# not a shebang", None),
+    ];
+    for (line, language) in cases {
+        assert_eq!(SourceLanguage::from_shebang(line), language, "{}", line);
+    }
+}
+
+#[test]
+fn test_find_code_extensionless_script() {
+    let mut skipped = Vec::new();
+    let sources = find_code("tests/python", &mut skipped).unwrap();
+    assert!(skipped.is_empty());
+    let release = sources
+        .iter()
+        .find(|code| code.filename == "tests/python/release")
+        .expect("the script is found by its shebang");
+    assert_eq!(release.language, SourceLanguage::Python);
+    let src_refs = extract_logging(&mut find_code("tests/python/release", &mut skipped).unwrap());
+    assert_eq!(src_refs.len(), 1);
+    assert_eq!(src_refs[0].vars, vec!["version"]);
 }
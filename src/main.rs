@@ -1,15 +1,60 @@
-use clap::Parser as ClapParser;
-use log2src::{do_mappings, extract_logging, filter_log, find_code, CallGraph, Filter};
+use clap::{error::ErrorKind, CommandFactory, Parser as ClapParser, ValueEnum};
+use log2src::{
+    count_matches_per_file, do_mappings, extract_logging_capped, filter_log, filter_source_refs,
+    find_code_at_rev, find_code_in, find_unused, first_hits, grammar_info, map_lines,
+    parse_message_bundle, sarif, self_check, CallGraph, ExtractOptions, Filter, LogMapping, LogRef,
+    MappingOptions, QualityWeights, SourceFilter, SourceRef, SourceStamps, ValueNormalizer,
+};
+use regex::Regex;
+use serde::Serialize;
 use serde_json::{self};
-use std::{error::Error, fs, io, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    io::{self, Write},
+    iter,
+    path::PathBuf,
+    process::ExitCode,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The exit code when more log lines than allowed by --fail-on-unmatched didn't map to source.
+const UNMATCHED_EXIT_CODE: u8 = 3;
+
+/// How long --follow waits before looking for lines appended to the log again.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How long --watch waits before looking for changes to the sources again.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// One JSON object per log line
+    Json,
+    /// Length-delimited protobuf messages, see proto/log2src.proto (needs the protobuf feature)
+    Protobuf,
+    /// A SARIF report with a result for each log statement that was hit, for code scanning
+    Sarif,
+    /// One flat JSON object of scalar columns per log line, with the variables encoded into
+    /// a string, for columnar stores like Parquet
+    FlatNdjson,
+}
 
 /// The log2src command maps log statements back to the source code that emitted them.
 #[derive(ClapParser)]
 #[command(author, version, about, long_about)]
 struct Cli {
-    /// A source directory (or soon directoires) to map logs onto
-    #[arg(short = 'd', long, value_name = "SOURCES")]
-    sources: String,
+    /// A source directory or file to map logs onto, repeat for more; a file under more than
+    /// one of them is only indexed once
+    #[arg(
+        short = 'd',
+        long,
+        value_name = "SOURCES",
+        required_unless_present_any = ["grammar_info", "self_check"]
+    )]
+    sources: Vec<String>,
 
     /// A log file to use, if not from stdin
     #[arg(short, long, value_name = "LOG")]
@@ -22,33 +67,549 @@ struct Cli {
     /// The last line of the log to use (0 based)
     #[arg(short, long, value_name = "END")]
     end: Option<usize>,
+
+    /// Keep reading lines appended to the log file, or piped in on stdin, and write their
+    /// mappings as they arrive, like `tail -f`, until the END line if one is given
+    #[arg(
+        short = 'F',
+        long,
+        conflicts_with_all = [
+            "first_hit_only",
+            "unused_statements",
+            "count_matches_per_file",
+            "summary",
+            "unmatched_to",
+            "fail_on_unmatched",
+        ]
+    )]
+    follow: bool,
+
+    /// Only match log statements in this source file, optionally within a line range
+    #[arg(long, value_name = "FILE[:START-END]")]
+    only: Option<SourceFilter>,
+
+    /// A Java .properties message bundle, to match statements that log messages.getString("key")
+    #[arg(long, value_name = "FILE")]
+    message_bundle: Option<PathBuf>,
+
+    /// Index the sources as they were at this git revision, e.g. the release that wrote the log
+    #[arg(long, value_name = "REV")]
+    git_rev: Option<String>,
+
+    /// With --follow, index the sources again when they change, so lines are matched against
+    /// the statements they have now; the calls listed for a statement still come from the start
+    #[arg(long, requires = "follow", conflicts_with = "git_rev")]
+    watch: bool,
+
+    /// Stop indexing log statements after this many, to sample a huge tree without a full scan
+    #[arg(long, value_name = "N")]
+    max_statements: Option<usize>,
+
+    /// The most log statements to try each line against, after --only, for predictable latency
+    /// on huge trees.  Mappings are flagged when the cap was hit
+    #[arg(long, value_name = "N")]
+    max_candidates: Option<usize>,
+
+    /// Another identifier that names a logger, so it is never captured as a variable
+    #[arg(long = "logger", value_name = "IDENT")]
+    loggers: Vec<String>,
+
+    /// Treat timestamps in the source's log messages as wildcards, for reformatted dates
+    #[arg(long)]
+    normalize_timestamps: bool,
+
+    /// Let the last placeholder in a message match as much as it can (the default)
+    #[arg(long, overrides_with = "placeholder_lazy")]
+    placeholder_greedy: bool,
+
+    /// Let the last placeholder in a message match as little as it can, e.g. so "path {} end"
+    /// captures "x" rather than "x end" from "path x end end"
+    #[arg(long, overrides_with = "placeholder_greedy")]
+    placeholder_lazy: bool,
+
+    /// Also match the messages given to anyhow's .context() and .with_context() in Rust
+    #[arg(long)]
+    include_error_context: bool,
+
+    /// Include the regex each log statement is matched with in its srcRef
+    #[arg(long)]
+    emit_pattern: bool,
+
+    /// Include the offset of each placeholder in a log statement's literal in its srcRef
+    #[arg(long)]
+    emit_placeholder_offsets: bool,
+
+    /// Include each log statement's position as an LSP position (0 based line, UTF-16
+    /// character) in its srcRef
+    #[arg(long)]
+    emit_lsp_positions: bool,
+
+    /// Include the name of the query capture each log statement came from in its srcRef
+    #[arg(long)]
+    emit_capture: bool,
+
+    /// Print the tree-sitter grammar bundled for each supported language and exit
+    #[arg(long)]
+    grammar_info: bool,
+
+    /// Check that each language's query still extracts log statements with the bundled grammar
+    #[arg(long)]
+    self_check: bool,
+
+    /// Also report variable values that are JSON objects or arrays as structured JSON
+    #[arg(long)]
+    parse_json_values: bool,
+
+    /// A regex for decoration the pipeline puts before each line, e.g. '[\w-]+ \| ', dropped
+    /// before matching
+    #[arg(long, value_name = "REGEX")]
+    line_prefix: Option<Regex>,
+
+    /// A regex for decoration the pipeline puts after each line, dropped before matching
+    #[arg(long, value_name = "REGEX")]
+    line_suffix: Option<Regex>,
+
+    /// A regex for the marker a logger appends to truncated messages, dropped before matching
+    #[arg(long, value_name = "REGEX")]
+    truncation_marker: Option<Regex>,
+
+    /// Rewrite each variable's value with a regex replacement, e.g. '0x[0-9a-f]+=0xADDR'
+    #[arg(long, value_name = "REGEX=REPLACEMENT")]
+    normalize_values: Vec<ValueNormalizer>,
+
+    /// Also list the calls to the function each matched log statement is in
+    #[arg(long)]
+    callers: bool,
+
+    /// How much each character of literal text counts when picking among matching statements
+    #[arg(
+        long,
+        value_name = "WEIGHT",
+        default_value_t = 1,
+        allow_negative_numbers = true
+    )]
+    literal_weight: i64,
+
+    /// How much each placeholder counts when picking among matching statements
+    #[arg(
+        long,
+        value_name = "WEIGHT",
+        default_value_t = 0,
+        allow_negative_numbers = true
+    )]
+    placeholder_weight: i64,
+
+    /// How much each run of literal text counts when picking among matching statements
+    #[arg(
+        long,
+        value_name = "WEIGHT",
+        default_value_t = 0,
+        allow_negative_numbers = true
+    )]
+    segment_weight: i64,
+
+    /// Only write the first log line that maps to each log statement
+    #[arg(long)]
+    first_hit_only: bool,
+
+    /// After the mappings, list the log statements that no log line mapped to, as a JSON
+    /// record with "type": "unusedStatements"
+    #[arg(long)]
+    unused_statements: bool,
+
+    /// After the mappings, report how many log lines mapped into each source file, as a JSON
+    /// record with "type": "matchesPerFile"
+    #[arg(long)]
+    count_matches_per_file: bool,
+
+    /// Print how many log lines were read, matched, and unmatched, and how many source files
+    /// they hit, to stderr
+    #[arg(long)]
+    summary: bool,
+
+    /// Exit with a failure if more than K log lines (default 0) don't map to source
+    #[arg(long, value_name = "K", num_args = 0..=1, default_missing_value = "0")]
+    fail_on_unmatched: Option<usize>,
+
+    /// Also write the raw log lines that didn't map to source to this file, to feed them to
+    /// another pass
+    #[arg(long, value_name = "FILE")]
+    unmatched_to: Option<PathBuf>,
+
+    /// Write the mappings to this file instead of stdout
+    #[arg(short = 'o', long, value_name = "FILE")]
+    output_file: Option<PathBuf>,
+
+    /// Indent the JSON for reading, instead of writing each record on one line
+    #[arg(long)]
+    json_pretty: bool,
+
+    /// How to write the mappings
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = OutputFormat::Json)]
+    output: OutputFormat,
+}
+
+// The reports share the output with the mappings, so each one says what it is in a `type`
+// field, which a mapping never has.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UnusedReport<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    unused_statements: Vec<&'a SourceRef>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MatchesPerFileReport<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    matches_per_file: BTreeMap<&'a str, usize>,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+// Writes one JSON record followed by a newline, so a pretty record still ends at a line that
+// closes it.
+fn write_json<T: Serialize>(out: &mut dyn Write, value: &T, pretty: bool) -> io::Result<()> {
+    let serialized = if pretty {
+        serde_json::to_string_pretty(value)?
+    } else {
+        serde_json::to_string(value)?
+    };
+    writeln!(out, "{}", serialized)
+}
+
+// Writes one mapping as a JSON record, flat or not, or a protobuf message when built with
+// that feature.
+// SARIF reports on all the mappings at once, so it isn't written here.
+fn write_mapping(
+    out: &mut dyn Write,
+    mapping: &LogMapping,
+    format: OutputFormat,
+    pretty: bool,
+) -> io::Result<()> {
+    match format {
+        #[cfg(feature = "protobuf")]
+        OutputFormat::Protobuf => out.write_all(&log2src::protobuf::encode(mapping)),
+        OutputFormat::FlatNdjson => write_json(out, &mapping.flatten(), pretty),
+        _ => write_json(out, mapping, pretty),
+    }
+}
+
+// Hands each line appended to the log to `emit`, like `tail -f`, until the line before `end`.
+// A line is only handed over once its newline arrives, so a line that is still being written
+// isn't matched in pieces.  `pending` is what was read of that line already, and `line_no`
+// the number of the line it starts.  At the end of a file more may be appended, so it waits
+// and reads again, while a pipe at its end has closed and won't have any more.
+fn follow_log(
+    reader: &mut dyn io::Read,
+    mut pending: Vec<u8>,
+    mut line_no: usize,
+    filter: &Filter,
+    wait_at_end: bool,
+    mut emit: impl FnMut(&LogRef) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut chunk = [0; 8192];
+    while line_no < filter.end {
+        let read = reader.read(&mut chunk)?;
+        if read == 0 {
+            if !wait_at_end {
+                // a closed pipe's last line may not end in a newline
+                if !pending.is_empty() && filter.start <= line_no && line_no < filter.end {
+                    let line = String::from_utf8_lossy(&pending);
+                    emit(&LogRef {
+                        line: line.trim_end_matches('\r'),
+                    })?;
+                }
+                break;
+            }
+            thread::sleep(FOLLOW_INTERVAL);
+            continue;
+        }
+        pending.extend_from_slice(&chunk[..read]);
+        while let Some(newline) = pending.iter().position(|&b| b == b'\n') {
+            let bytes = pending.drain(..=newline).collect::<Vec<u8>>();
+            let line = String::from_utf8_lossy(&bytes);
+            let line = line.trim_end_matches('\n').trim_end_matches('\r');
+            if filter.start <= line_no && line_no < filter.end {
+                emit(&LogRef { line })?;
+            }
+            line_no += 1;
+        }
+    }
+    Ok(())
+}
+
+// Indexes the log statements in the sources again, for --watch.
+fn rescan(
+    roots: &[String],
+    options: &ExtractOptions,
+    only: Option<&SourceFilter>,
+) -> Result<Vec<SourceRef>, Box<dyn Error>> {
+    let mut skipped = Vec::new();
+    let mut sources = find_code_in(roots, &mut skipped)?;
+    for unreadable in &skipped {
+        eprintln!("log2src: skipping {}", unreadable);
+    }
+    let (mut src_logs, _) = extract_logging_capped(&mut sources, options)?;
+    if let Some(only) = only {
+        filter_source_refs(&mut src_logs, only);
+    }
+    Ok(src_logs)
+}
+
+fn main() -> Result<ExitCode, Box<dyn Error>> {
     let args = Cli::parse();
+    if args.grammar_info {
+        println!(
+            "tree-sitter supports grammar ABI versions {} to {}",
+            tree_sitter::MIN_COMPATIBLE_LANGUAGE_VERSION,
+            tree_sitter::LANGUAGE_VERSION
+        );
+        for grammar in grammar_info() {
+            println!(
+                "{}: ABI version {}, crate version {}",
+                grammar.language, grammar.abi_version, grammar.crate_version
+            );
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    if args.self_check {
+        self_check()?;
+        println!("self check passed");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    #[cfg(not(feature = "protobuf"))]
+    if args.output == OutputFormat::Protobuf {
+        return Err("log2src was built without the protobuf feature".into());
+    }
+    if args.follow && args.output == OutputFormat::Sarif {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--follow can't write a SARIF report, which covers the whole log",
+            )
+            .exit();
+    }
+    // the reports are JSON records written after the mappings, which only fits JSON output
+    if args.output != OutputFormat::Json && (args.unused_statements || args.count_matches_per_file)
+    {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--unused-statements and --count-matches-per-file need --output json",
+            )
+            .exit();
+    }
+
     let input = args.log;
-    let mut reader: Box<dyn io::Read> = match input {
+    let mut reader: Box<dyn io::Read> = match &input {
         None => Box::new(io::stdin()),
         Some(filename) => Box::new(fs::File::open(filename).expect("Can open file")),
     };
 
     let mut buffer = String::new();
-    reader.read_to_string(&mut buffer)?;
-    let filter = Filter {
-        start: args.start.unwrap_or(0),
-        end: args.end.unwrap_or(usize::MAX),
-    };
-    let filtered = filter_log(&buffer, filter);
+    // stdin is only at its end once it's closed, so following it starts streaming right away
+    if !(args.follow && input.is_none()) {
+        reader.read_to_string(&mut buffer)?;
+    }
+    // the last line may still be being written, so following picks it up once it's finished
+    let mut pending = Vec::new();
+    if args.follow && !buffer.ends_with('\n') {
+        let start = buffer.rfind('\n').map_or(0, |i| i + 1);
+        pending = buffer.split_off(start).into_bytes();
+    }
+    let filtered = filter_log(
+        &buffer,
+        Filter {
+            start: args.start.unwrap_or(0),
+            end: args.end.unwrap_or(usize::MAX),
+        },
+    );
 
-    let mut sources = find_code(&args.sources);
-    let src_logs = extract_logging(&mut sources);
+    let message_bundle = match &args.message_bundle {
+        None => Default::default(),
+        Some(path) => match fs::read_to_string(path) {
+            Ok(text) => parse_message_bundle(&text),
+            Err(e) => return Err(format!("cannot read {}: {}", path.display(), e).into()),
+        },
+    };
+    // taken before the scan, so a change made while it runs is seen by the first check
+    let mut stamps = args.watch.then(|| SourceStamps::take(&args.sources));
+    let mut last_check = Instant::now();
+    let mut skipped = Vec::new();
+    let mut sources = match &args.git_rev {
+        None => find_code_in(&args.sources, &mut skipped)?,
+        Some(rev) => find_code_at_rev(&args.sources, rev, &mut skipped)?,
+    };
+    for unreadable in &skipped {
+        eprintln!("log2src: skipping {}", unreadable);
+    }
+    let extract_options = ExtractOptions {
+        loggers: args.loggers,
+        normalize_timestamps: args.normalize_timestamps,
+        emit_pattern: args.emit_pattern,
+        emit_placeholder_offsets: args.emit_placeholder_offsets,
+        emit_lsp_positions: args.emit_lsp_positions,
+        emit_capture: args.emit_capture,
+        lazy_placeholders: args.placeholder_lazy && !args.placeholder_greedy,
+        include_error_context: args.include_error_context,
+        max_statements: args.max_statements,
+        message_bundle,
+    };
+    let (mut src_logs, index_truncated) = extract_logging_capped(&mut sources, &extract_options)?;
+    if let Some(only) = &args.only {
+        filter_source_refs(&mut src_logs, only);
+    }
+    if let Some(max) = args.max_candidates.filter(|&max| max < src_logs.len()) {
+        eprintln!(
+            "log2src: matching limited to the first {} of {} log statements",
+            max,
+            src_logs.len()
+        );
+    }
     let call_graph = CallGraph::new(&mut sources);
-    let log_mappings = do_mappings(&filtered, &src_logs, &call_graph);
+    let mapping_options = MappingOptions {
+        parse_json_values: args.parse_json_values,
+        line_prefix: args.line_prefix,
+        line_suffix: args.line_suffix,
+        truncation_marker: args.truncation_marker,
+        normalize_values: args.normalize_values,
+        callers: args.callers,
+        quality_weights: QualityWeights {
+            literal: args.literal_weight,
+            placeholders: args.placeholder_weight,
+            segments: args.segment_weight,
+        },
+        index_truncated,
+        max_candidates: args.max_candidates,
+    };
+    let log_mappings = do_mappings(&filtered, &src_logs, &call_graph, &mapping_options);
+
+    let mut out: Box<dyn Write> = match &args.output_file {
+        None => Box::new(io::stdout().lock()),
+        Some(path) => match fs::File::create(path) {
+            Ok(file) => Box::new(io::BufWriter::new(file)),
+            Err(e) => return Err(format!("cannot create {}: {}", path.display(), e).into()),
+        },
+    };
+    let written = if args.first_hit_only {
+        first_hits(&log_mappings)
+    } else {
+        log_mappings.iter().collect()
+    };
+    match args.output {
+        OutputFormat::Sarif => {
+            write_json(&mut out, &sarif::report(&log_mappings), args.json_pretty)?;
+        }
+        _ => {
+            for mapping in written {
+                write_mapping(&mut out, mapping, args.output, args.json_pretty)?;
+            }
+        }
+    }
+    if args.follow {
+        out.flush()?;
+        let filter = Filter {
+            start: args.start.unwrap_or(0),
+            end: args.end.unwrap_or(usize::MAX),
+        };
+        let mut rescanned: Option<Vec<SourceRef>> = None;
+        follow_log(
+            &mut reader,
+            pending,
+            buffer.lines().count(),
+            &filter,
+            input.is_some(),
+            |log_ref| {
+                if let Some(stamps) = stamps.as_mut() {
+                    if last_check.elapsed() >= WATCH_INTERVAL {
+                        last_check = Instant::now();
+                        let now = SourceStamps::take(&args.sources);
+                        if now != *stamps {
+                            *stamps = now;
+                            // a tree caught half-written keeps the statements found before
+                            match rescan(&args.sources, &extract_options, args.only.as_ref()) {
+                                Ok(src_logs) => rescanned = Some(src_logs),
+                                Err(e) => eprintln!("log2src: cannot rescan the sources: {}", e),
+                            }
+                        }
+                    }
+                }
+                let mappings = map_lines(
+                    iter::once(log_ref),
+                    rescanned.as_deref().unwrap_or(&src_logs),
+                    &call_graph,
+                    &mapping_options,
+                );
+                for mapping in mappings {
+                    write_mapping(&mut out, &mapping, args.output, args.json_pretty)?;
+                }
+                out.flush()
+            },
+        )?;
+    }
 
-    for mapping in log_mappings {
-        let serialized = serde_json::to_string(&mapping).unwrap();
-        println!("{}", serialized);
+    if args.unused_statements {
+        let unused = find_unused(&log_mappings, &src_logs);
+        let report = UnusedReport {
+            kind: "unusedStatements",
+            unused_statements: unused,
+        };
+        write_json(&mut out, &report, args.json_pretty)?;
     }
+    if args.count_matches_per_file {
+        let counts = count_matches_per_file(&log_mappings);
+        let report = MatchesPerFileReport {
+            kind: "matchesPerFile",
+            matches_per_file: counts,
+        };
+        write_json(&mut out, &report, args.json_pretty)?;
+    }
+    out.flush()?;
 
-    Ok(())
+    if let Some(path) = &args.unmatched_to {
+        let mut unmatched_out = match fs::File::create(path) {
+            Ok(file) => io::BufWriter::new(file),
+            Err(e) => return Err(format!("cannot create {}: {}", path.display(), e).into()),
+        };
+        for mapping in log_mappings
+            .iter()
+            .filter(|mapping| mapping.src_ref.is_none())
+        {
+            writeln!(unmatched_out, "{}", mapping.log_ref.line)?;
+        }
+        unmatched_out.flush()?;
+    }
+
+    if args.summary {
+        let matched = log_mappings
+            .iter()
+            .filter(|mapping| mapping.src_ref.is_some())
+            .count();
+        eprintln!(
+            "log2src: {} log lines, {} matched, {} unmatched, {} source files hit",
+            log_mappings.len(),
+            matched,
+            log_mappings.len() - matched,
+            count_matches_per_file(&log_mappings).len()
+        );
+    }
+
+    if let Some(allowed) = args.fail_on_unmatched {
+        let unmatched = log_mappings
+            .iter()
+            .filter(|mapping| mapping.src_ref.is_none())
+            .count();
+        if unmatched > allowed {
+            eprintln!(
+                "log2src: {} of {} log lines did not map to source",
+                unmatched,
+                log_mappings.len()
+            );
+            return Ok(ExitCode::from(UNMATCHED_EXIT_CODE));
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
 }
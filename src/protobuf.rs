@@ -0,0 +1,225 @@
+//! Length-delimited protobuf encoding of `LogMapping`s for high-volume pipelines, where
+//! serializing JSON dominates the cost of a run.  The messages mirror `proto/log2src.proto`.
+
+use crate::{LogMapping, LspPosition, SourceRef};
+use prost::Message;
+use std::collections::HashMap;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SourceRefMessage {
+    #[prost(string, tag = "1")]
+    pub source_path: String,
+    #[prost(uint64, tag = "2")]
+    pub line_number: u64,
+    #[prost(uint64, tag = "3")]
+    pub column: u64,
+    #[prost(string, tag = "4")]
+    pub name: String,
+    #[prost(string, tag = "5")]
+    pub text: String,
+    #[prost(string, repeated, tag = "6")]
+    pub vars: Vec<String>,
+    #[prost(string, optional, tag = "7")]
+    pub pattern: Option<String>,
+    #[prost(uint64, repeated, tag = "8")]
+    pub placeholder_offsets: Vec<u64>,
+    #[prost(message, optional, tag = "9")]
+    pub position: Option<LspPositionMessage>,
+    #[prost(string, optional, tag = "10")]
+    pub capture: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct LspPositionMessage {
+    #[prost(uint64, tag = "1")]
+    pub line: u64,
+    #[prost(uint64, tag = "2")]
+    pub character: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CallPathMessage {
+    #[prost(message, repeated, tag = "1")]
+    pub frames: Vec<SourceRefMessage>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct LogMappingMessage {
+    #[prost(message, optional, tag = "1")]
+    pub src_ref: Option<SourceRefMessage>,
+    #[prost(map = "string, string", tag = "2")]
+    pub variables: HashMap<String, String>,
+    #[prost(message, repeated, tag = "3")]
+    pub stack: Vec<CallPathMessage>,
+    /// The variables that hold JSON, each as its JSON text.
+    #[prost(map = "string, string", tag = "4")]
+    pub json_values: HashMap<String, String>,
+    #[prost(message, repeated, tag = "5")]
+    pub callers: Vec<SourceRefMessage>,
+    #[prost(bool, tag = "6")]
+    pub truncated: bool,
+    #[prost(uint64, optional, tag = "7")]
+    pub repeat_count: Option<u64>,
+    #[prost(bool, tag = "8")]
+    pub index_truncated: bool,
+}
+
+impl From<&SourceRef> for SourceRefMessage {
+    fn from(src_ref: &SourceRef) -> Self {
+        SourceRefMessage {
+            source_path: src_ref.source_path.clone(),
+            line_number: src_ref.line_no as u64,
+            column: src_ref.column as u64,
+            name: src_ref.name.clone(),
+            text: src_ref.text.clone(),
+            vars: src_ref.vars.clone(),
+            pattern: src_ref.pattern.clone(),
+            placeholder_offsets: src_ref
+                .placeholder_offsets
+                .iter()
+                .flatten()
+                .map(|&offset| offset as u64)
+                .collect(),
+            position: src_ref.position.as_ref().map(LspPositionMessage::from),
+            capture: src_ref.capture.clone(),
+        }
+    }
+}
+
+impl From<&LspPosition> for LspPositionMessage {
+    fn from(position: &LspPosition) -> Self {
+        LspPositionMessage {
+            line: position.line as u64,
+            character: position.character as u64,
+        }
+    }
+}
+
+impl From<&LogMapping<'_>> for LogMappingMessage {
+    fn from(mapping: &LogMapping) -> Self {
+        LogMappingMessage {
+            src_ref: mapping.src_ref.map(SourceRefMessage::from),
+            variables: mapping
+                .variables
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            stack: mapping
+                .stack
+                .iter()
+                .map(|path| CallPathMessage {
+                    frames: path
+                        .iter()
+                        .map(|&frame| SourceRefMessage::from(frame))
+                        .collect(),
+                })
+                .collect(),
+            json_values: mapping
+                .json_values
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            callers: mapping
+                .callers
+                .iter()
+                .map(|&caller| SourceRefMessage::from(caller))
+                .collect(),
+            truncated: mapping.truncated,
+            repeat_count: mapping.repeated.map(|repeated| repeated as u64),
+            index_truncated: mapping.index_truncated,
+        }
+    }
+}
+
+/// Encodes a mapping as a length-delimited protobuf message.
+pub fn encode(mapping: &LogMapping) -> Vec<u8> {
+    LogMappingMessage::from(mapping).encode_length_delimited_to_vec()
+}
+
+#[test]
+fn test_round_trip() {
+    use crate::{
+        do_mappings, extract_logging_with, filter_log, CallGraph, CodeSource, ExtractOptions,
+        Filter, MappingOptions,
+    };
+    use regex::Regex;
+    use std::path::PathBuf;
+
+    let code = CodeSource::new(
+        PathBuf::from("in-mem.rs"),
+        Box::new(crate::TEST_SOURCE.as_bytes()),
+    );
+    let mut sources = vec![code];
+    let extract_options = ExtractOptions {
+        emit_pattern: true,
+        emit_placeholder_offsets: true,
+        emit_lsp_positions: true,
+        emit_capture: true,
+        ..ExtractOptions::default()
+    };
+    let src_refs = extract_logging_with(&mut sources, &extract_options).unwrap();
+    let call_graph = CallGraph::new(&mut sources);
+    let buffer = String::from(concat!(
+        "[2024-02-15T03:46:44Z DEBUG nope] this won't match i=1\n",
+        "nope\n",
+        "Feb 15 03:46:44 host app: message repeated 3 times: [ this won't match i={\"n\":1} ...]",
+    ));
+    let log_refs = filter_log(&buffer, Filter::default());
+    let mapping_options = MappingOptions {
+        parse_json_values: true,
+        truncation_marker: Some(Regex::new(r#" \.\.\."#).unwrap()),
+        callers: true,
+        index_truncated: true,
+        ..MappingOptions::default()
+    };
+    let mappings = do_mappings(&log_refs, &src_refs, &call_graph, &mapping_options);
+
+    let mut encoded = Vec::new();
+    for mapping in mappings.iter() {
+        encoded.extend(encode(mapping));
+    }
+    let mut buf = encoded.as_slice();
+    let decoded = (0..mappings.len())
+        .map(|_| LogMappingMessage::decode_length_delimited(&mut buf).unwrap())
+        .collect::<Vec<_>>();
+    assert!(buf.is_empty());
+    for (message, mapping) in decoded.iter().zip(&mappings) {
+        assert_eq!(message, &LogMappingMessage::from(mapping));
+    }
+
+    let first = &decoded[0];
+    let src_ref = first.src_ref.as_ref().unwrap();
+    assert_eq!(src_ref.line_number, 18);
+    assert_eq!(src_ref.name, "nope");
+    assert_eq!(
+        src_ref.pattern.as_deref(),
+        Some(r#"this won't match i=(.+)"#)
+    );
+    assert_eq!(src_ref.placeholder_offsets, vec![20]);
+    assert_eq!(
+        src_ref.position,
+        Some(LspPositionMessage {
+            line: 17,
+            character: 11
+        })
+    );
+    assert_eq!(src_ref.capture.as_deref(), Some("log"));
+    assert_eq!(first.variables.get("i"), Some(&String::from("1")));
+    assert_eq!(first.stack.len(), 1);
+    assert_eq!(first.callers.len(), 1);
+    assert_eq!(first.callers[0].name, "foo");
+    assert!(!first.truncated);
+    assert_eq!(first.repeat_count, None);
+    assert!(first.index_truncated);
+
+    assert_eq!(decoded[1].src_ref, None);
+    assert!(decoded[1].index_truncated);
+
+    let third = &decoded[2];
+    assert_eq!(
+        third.json_values.get("i"),
+        Some(&String::from(r#"{"n":1}"#))
+    );
+    assert!(third.truncated);
+    assert_eq!(third.repeat_count, Some(3));
+}
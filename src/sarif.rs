@@ -0,0 +1,187 @@
+//! A SARIF 2.1.0 report of the log statements that log lines mapped to, so the hits show up
+//! in code scanning UIs.  Each statement that was hit is one result, located at its call.
+
+use crate::{LogMapping, SourceRef};
+use serde::Serialize;
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const RULE_ID: &str = "log-statement-hit";
+
+#[derive(Serialize)]
+pub struct SarifLog {
+    #[serde(rename = "$schema")]
+    pub schema: &'static str,
+    pub version: &'static str,
+    pub runs: Vec<Run>,
+}
+
+#[derive(Serialize)]
+pub struct Run {
+    pub tool: Tool,
+    pub results: Vec<SarifResult>,
+}
+
+#[derive(Serialize)]
+pub struct Tool {
+    pub driver: Driver,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Driver {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rule {
+    pub id: &'static str,
+    pub short_description: Message,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifResult {
+    pub rule_id: &'static str,
+    pub level: &'static str,
+    pub message: Message,
+    pub locations: Vec<Location>,
+}
+
+#[derive(Serialize)]
+pub struct Message {
+    pub text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub physical_location: PhysicalLocation,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhysicalLocation {
+    pub artifact_location: ArtifactLocation,
+    pub region: Region,
+}
+
+#[derive(Serialize)]
+pub struct ArtifactLocation {
+    pub uri: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Region {
+    pub start_line: usize,
+    pub start_column: usize,
+}
+
+impl SarifResult {
+    fn new(src_ref: &SourceRef, hits: usize) -> Self {
+        SarifResult {
+            rule_id: RULE_ID,
+            level: "note",
+            message: Message {
+                text: format!("{} logged {} time(s)", src_ref.text, hits),
+            },
+            locations: vec![Location {
+                physical_location: PhysicalLocation {
+                    artifact_location: ArtifactLocation {
+                        uri: src_ref.source_path.replace('\\', "/"),
+                    },
+                    region: Region {
+                        start_line: src_ref.line_no,
+                        // SARIF columns are 1 based
+                        start_column: src_ref.column + 1,
+                    },
+                },
+            }],
+        }
+    }
+}
+
+/// Builds a report with a result for each log statement that at least one mapping hit, in the
+/// order they were first hit.
+pub fn report(mappings: &[LogMapping]) -> SarifLog {
+    let mut hits: Vec<(&SourceRef, usize)> = Vec::new();
+    for mapping in mappings {
+        let Some(src_ref) = mapping.src_ref else {
+            continue;
+        };
+        let lines = mapping.repeated.unwrap_or(1);
+        match hits.iter_mut().find(|(hit, _)| std::ptr::eq(*hit, src_ref)) {
+            Some((_, count)) => *count += lines,
+            None => hits.push((src_ref, lines)),
+        }
+    }
+    SarifLog {
+        schema: SCHEMA,
+        version: "2.1.0",
+        runs: vec![Run {
+            tool: Tool {
+                driver: Driver {
+                    name: "log2src",
+                    version: env!("CARGO_PKG_VERSION"),
+                    rules: vec![Rule {
+                        id: RULE_ID,
+                        short_description: Message {
+                            text: String::from("A log line was mapped to this log statement"),
+                        },
+                    }],
+                },
+            },
+            results: hits
+                .into_iter()
+                .map(|(src_ref, count)| SarifResult::new(src_ref, count))
+                .collect(),
+        }],
+    }
+}
+
+#[test]
+fn test_report() {
+    use crate::{
+        do_mappings, extract_logging, filter_log, CallGraph, CodeSource, Filter, MappingOptions,
+    };
+    use std::path::PathBuf;
+
+    let code = CodeSource::new(
+        PathBuf::from("in-mem.rs"),
+        Box::new(crate::TEST_SOURCE.as_bytes()),
+    );
+    let mut sources = vec![code];
+    let src_refs = extract_logging(&mut sources);
+    let call_graph = CallGraph::new(&mut sources);
+    let buffer = String::from(
+        "[2024-02-15T03:46:44Z DEBUG nope] this won't match i=1\n\
+         [2024-02-15T03:46:44Z DEBUG nope] this won't match i=2\n\
+         nope",
+    );
+    let log_refs = filter_log(&buffer, Filter::default());
+    let mappings = do_mappings(
+        &log_refs,
+        &src_refs,
+        &call_graph,
+        &MappingOptions::default(),
+    );
+
+    let sarif = serde_json::to_value(report(&mappings)).unwrap();
+    assert_eq!(sarif["version"], "2.1.0");
+    let run = &sarif["runs"][0];
+    assert_eq!(run["tool"]["driver"]["name"], "log2src");
+    let results = run["results"].as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["ruleId"], RULE_ID);
+    assert_eq!(
+        results[0]["message"]["text"],
+        "\"this won't match i={}\" logged 2 time(s)"
+    );
+    let location = &results[0]["locations"][0]["physicalLocation"];
+    assert_eq!(location["artifactLocation"]["uri"], "in-mem.rs");
+    assert_eq!(location["region"]["startLine"], 18);
+    assert_eq!(location["region"]["startColumn"], 12);
+}
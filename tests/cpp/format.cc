@@ -0,0 +1,14 @@
+#include <spdlog/spdlog.h>
+
+#include <string>
+
+void accept(int id, const std::string& peer) {
+    auto message = fmt::format("accepted {} from {}", id, peer);
+    spdlog::info(message);
+}
+
+int main() {
+    accept(7, "10.0.0.2");
+    accept(8, "10.0.0.3");
+    return 0;
+}
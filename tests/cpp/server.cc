@@ -0,0 +1,16 @@
+#include <glog/logging.h>
+
+#include <string>
+
+void serve(const std::string& user, int requests) {
+    LOG(INFO) << "serving " << user;
+    for (int i = 0; i < requests; i++) {
+        LOG(WARNING) << "request " << i << " of " << user << " was slow";
+    }
+}
+
+int main(int argc, char* argv[]) {
+    google::InitGoogleLogging(argv[0]);
+    serve("alice", 2);
+    return 0;
+}
@@ -0,0 +1,13 @@
+package main
+
+import "log"
+
+func greet(name string, age int) {
+	log.Printf("user %s age %d", name, age)
+}
+
+func main() {
+	greet("alice", 42)
+	greet("bob", 7)
+	log.Println("done")
+}
@@ -0,0 +1,14 @@
+import java.util.ResourceBundle;
+import org.slf4j.Logger;
+import org.slf4j.LoggerFactory;
+
+public class Bundle {
+    private static final Logger logger = LoggerFactory.getLogger(Bundle.class);
+    private static final ResourceBundle messages = ResourceBundle.getBundle("messages");
+
+    public static void main(String[] args) {
+        String user = args[0];
+        logger.info(messages.getString("user.login"), user);
+        logger.warn(messages.getString("user.unknown"));
+    }
+}
@@ -0,0 +1,13 @@
+import java.util.logging.*;
+
+public class StaticInit {
+    private static final Logger logger = Logger.getLogger("static");
+
+    static {
+        logger.info("Loaded the configuration");
+    }
+
+    public static void main(String[] args) {
+        logger.info("Hello from main");
+    }
+}
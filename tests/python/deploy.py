@@ -0,0 +1,14 @@
+import logging
+
+logger = logging.getLogger(__name__)
+
+
+def deploy(user, action):
+    logger.info("user {} did {}".format(user, action))
+    for attempt in range(3):
+        logger.warning(f"retry {attempt} of {action}")
+
+
+if __name__ == "__main__":
+    logging.basicConfig(level=logging.INFO)
+    deploy("alice", "rollback")
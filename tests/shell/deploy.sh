@@ -0,0 +1,10 @@
+#!/bin/sh
+
+deploy() {
+    app=$1
+    echo "deploying $app"
+    printf "took %d seconds\n" "$2"
+}
+
+deploy web 12
+deploy api 3
@@ -0,0 +1,53 @@
+use assert_cmd::prelude::*;
+use std::{path::Path, process::Command};
+
+#[test]
+fn stream() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let server_source = Path::new("tests").join("cpp").join("server.cc");
+    let server_log = Path::new("tests")
+        .join("resources")
+        .join("cpp")
+        .join("server.log");
+    cmd.arg("-d")
+        .arg(
+            server_source
+                .to_str()
+                .expect("test case source code exists"),
+        )
+        .arg("-l")
+        .arg(server_log.to_str().expect("test case log exists"))
+        // the flat output writes the variables in order
+        .arg("--output")
+        .arg("flat-ndjson");
+    cmd.assert().success().stdout(r#"{"line":"I20240509 19:58:53.117023  4242 server.cc:6] serving alice","timestamp":null,"level":null,"thread":null,"matched":true,"confidence":0.6153846153846154,"sourcePath":"tests/cpp/server.cc","lineNumber":6,"functionName":"serve","variables":"{\"user\":\"alice\"}","repeated":null,"truncated":false,"indexTruncated":false}
+{"line":"W20240509 19:58:53.117310  4242 server.cc:8] request 0 of alice was slow","timestamp":null,"level":null,"thread":null,"matched":true,"confidence":0.7777777777777778,"sourcePath":"tests/cpp/server.cc","lineNumber":8,"functionName":"serve","variables":"{\"i\":\"0\",\"user\":\"alice\"}","repeated":null,"truncated":false,"indexTruncated":false}
+{"line":"W20240509 19:58:53.117402  4242 server.cc:8] request 1 of alice was slow","timestamp":null,"level":null,"thread":null,"matched":true,"confidence":0.7777777777777778,"sourcePath":"tests/cpp/server.cc","lineNumber":8,"functionName":"serve","variables":"{\"i\":\"1\",\"user\":\"alice\"}","repeated":null,"truncated":false,"indexTruncated":false}
+"#);
+    Ok(())
+}
+
+#[test]
+fn format_binding() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let format_source = Path::new("tests").join("cpp").join("format.cc");
+    let format_log = Path::new("tests")
+        .join("resources")
+        .join("cpp")
+        .join("format.log");
+    cmd.arg("-d")
+        .arg(
+            format_source
+                .to_str()
+                .expect("test case source code exists"),
+        )
+        .arg("-l")
+        .arg(format_log.to_str().expect("test case log exists"))
+        // the flat output writes the variables in order
+        .arg("--output")
+        .arg("flat-ndjson");
+    cmd.assert().success().stdout(r#"{"line":"[2024-05-09 19:58:53.117] [info] accepted 7 from 10.0.0.2","timestamp":"2024-05-09 19:58:53.117","level":"info","thread":null,"matched":true,"confidence":0.625,"sourcePath":"tests/cpp/format.cc","lineNumber":6,"functionName":"accept","variables":"{\"id\":\"7\",\"peer\":\"10.0.0.2\"}","repeated":null,"truncated":false,"indexTruncated":false}
+{"line":"[2024-05-09 19:58:53.118] [info] accepted 8 from 10.0.0.3","timestamp":"2024-05-09 19:58:53.118","level":"info","thread":null,"matched":true,"confidence":0.625,"sourcePath":"tests/cpp/format.cc","lineNumber":6,"functionName":"accept","variables":"{\"id\":\"8\",\"peer\":\"10.0.0.3\"}","repeated":null,"truncated":false,"indexTruncated":false}
+"#);
+    Ok(())
+}
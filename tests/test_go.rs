@@ -0,0 +1,24 @@
+use assert_cmd::prelude::*;
+use std::{path::Path, process::Command};
+
+#[test]
+fn printf() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let main_source = Path::new("tests").join("go").join("main.go");
+    let main_log = Path::new("tests")
+        .join("resources")
+        .join("go")
+        .join("main.log");
+    cmd.arg("-d")
+        .arg(main_source.to_str().expect("test case source code exists"))
+        .arg("-l")
+        .arg(main_log.to_str().expect("test case log exists"))
+        // the flat output writes the variables in order
+        .arg("--output")
+        .arg("flat-ndjson");
+    cmd.assert().success().stdout(r#"{"line":"2024/05/09 19:58:53 user alice age 42","timestamp":null,"level":null,"thread":null,"matched":true,"confidence":0.5882352941176471,"sourcePath":"tests/go/main.go","lineNumber":6,"functionName":"greet","variables":"{\"age\":\"42\",\"name\":\"alice\"}","repeated":null,"truncated":false,"indexTruncated":false}
+{"line":"2024/05/09 19:58:53 user bob age 7","timestamp":null,"level":null,"thread":null,"matched":true,"confidence":0.7142857142857143,"sourcePath":"tests/go/main.go","lineNumber":6,"functionName":"greet","variables":"{\"age\":\"7\",\"name\":\"bob\"}","repeated":null,"truncated":false,"indexTruncated":false}
+{"line":"2024/05/09 19:58:53 done","timestamp":null,"level":null,"thread":null,"matched":true,"confidence":1.0,"sourcePath":"tests/go/main.go","lineNumber":12,"functionName":"main","variables":"{}","repeated":null,"truncated":false,"indexTruncated":false}
+"#);
+    Ok(())
+}
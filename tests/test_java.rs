@@ -60,3 +60,46 @@ fn basic_with_upper() -> Result<(), Box<dyn std::error::Error>> {
 "#);
     Ok(())
 }
+
+#[test]
+fn static_init() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let static_source = Path::new("tests").join("java").join("StaticInit.java");
+    let static_log = Path::new("tests")
+        .join("resources")
+        .join("java")
+        .join("static.log");
+    cmd.arg("-d")
+        .arg(
+            static_source
+                .to_str()
+                .expect("test case source code exists"),
+        )
+        .arg("-l")
+        .arg(static_log.to_str().expect("test case log exists"));
+    cmd.assert().success().stdout(r#"{"srcRef":{"sourcePath":"tests/java/StaticInit.java","lineNumber":7,"column":20,"name":"<clinit>","text":"\"Loaded the configuration\"","vars":[]},"variables":{},"stack":[]}
+{"srcRef":{"sourcePath":"tests/java/StaticInit.java","lineNumber":11,"column":20,"name":"main","text":"\"Hello from main\"","vars":[]},"variables":{},"stack":[]}
+"#);
+    Ok(())
+}
+
+#[test]
+fn message_bundle() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let bundle_source = Path::new("tests").join("java").join("Bundle.java");
+    let resources = Path::new("tests").join("resources").join("java");
+    cmd.arg("-d")
+        .arg(
+            bundle_source
+                .to_str()
+                .expect("test case source code exists"),
+        )
+        .arg("-l")
+        .arg(resources.join("bundle.log"))
+        .arg("--message-bundle")
+        .arg(resources.join("messages.properties"));
+    cmd.assert().success().stdout(r#"{"srcRef":{"sourcePath":"tests/java/Bundle.java","lineNumber":11,"column":39,"name":"main","text":"\"user.login\"","vars":["user"]},"variables":{"user":"alice"},"stack":[]}
+{"srcRef":{"sourcePath":"tests/java/Bundle.java","lineNumber":12,"column":39,"name":"main","text":"\"user.unknown\"","vars":[]},"variables":{},"stack":[]}
+"#);
+    Ok(())
+}
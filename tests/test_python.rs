@@ -0,0 +1,50 @@
+use assert_cmd::prelude::*;
+use std::{path::Path, process::Command};
+
+#[test]
+fn format() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let deploy_source = Path::new("tests").join("python").join("deploy.py");
+    let deploy_log = Path::new("tests")
+        .join("resources")
+        .join("python")
+        .join("deploy.log");
+    cmd.arg("-d")
+        .arg(
+            deploy_source
+                .to_str()
+                .expect("test case source code exists"),
+        )
+        .arg("-l")
+        .arg(deploy_log.to_str().expect("test case log exists"))
+        // the flat output writes the variables in order
+        .arg("--output")
+        .arg("flat-ndjson");
+    cmd.assert().success().stdout(r#"{"line":"INFO:__main__:user alice did rollback","timestamp":null,"level":"INFO","thread":null,"matched":true,"confidence":0.43478260869565216,"sourcePath":"tests/python/deploy.py","lineNumber":7,"functionName":"deploy","variables":"{\"action\":\"rollback\",\"user\":\"alice\"}","repeated":null,"truncated":false,"indexTruncated":false}
+{"line":"WARNING:__main__:retry 0 of rollback","timestamp":null,"level":"WARNING","thread":null,"matched":true,"confidence":0.5263157894736842,"sourcePath":"tests/python/deploy.py","lineNumber":9,"functionName":"deploy","variables":"{\"action\":\"rollback\",\"attempt\":\"0\"}","repeated":null,"truncated":false,"indexTruncated":false}
+{"line":"WARNING:__main__:retry 1 of rollback","timestamp":null,"level":"WARNING","thread":null,"matched":true,"confidence":0.5263157894736842,"sourcePath":"tests/python/deploy.py","lineNumber":9,"functionName":"deploy","variables":"{\"action\":\"rollback\",\"attempt\":\"1\"}","repeated":null,"truncated":false,"indexTruncated":false}
+{"line":"WARNING:__main__:retry 2 of rollback","timestamp":null,"level":"WARNING","thread":null,"matched":true,"confidence":0.5263157894736842,"sourcePath":"tests/python/deploy.py","lineNumber":9,"functionName":"deploy","variables":"{\"action\":\"rollback\",\"attempt\":\"2\"}","repeated":null,"truncated":false,"indexTruncated":false}
+"#);
+    Ok(())
+}
+
+#[test]
+fn extensionless_script() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let release_source = Path::new("tests").join("python").join("release");
+    let release_log = Path::new("tests")
+        .join("resources")
+        .join("python")
+        .join("release.log");
+    cmd.arg("-d")
+        .arg(
+            release_source
+                .to_str()
+                .expect("test case source code exists"),
+        )
+        .arg("-l")
+        .arg(release_log.to_str().expect("test case log exists"));
+    cmd.assert().success().stdout(r#"{"srcRef":{"sourcePath":"tests/python/release","lineNumber":9,"column":16,"name":"release","text":"\"releasing {}\"","vars":["version"]},"variables":{"version":"1.2.0"},"stack":[]}
+"#);
+    Ok(())
+}
@@ -1,5 +1,12 @@
 use assert_cmd::prelude::*;
-use std::{path::Path, process::Command};
+use std::{
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    process::{Child, Command, Stdio},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
 
 #[test]
 fn basic() -> Result<(), Box<dyn std::error::Error>> {
@@ -39,3 +46,631 @@ fn stack() -> Result<(), Box<dyn std::error::Error>> {
 "#);
     Ok(())
 }
+
+#[test]
+fn unused_statements() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let source = Path::new("examples").join("stack.rs");
+    let log = Path::new("tests")
+        .join("resources")
+        .join("rust")
+        .join("stack.log");
+    cmd.arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("-l")
+        .arg(log.to_str().expect("test case log path is valid"))
+        .arg("-s")
+        .arg("1")
+        .arg("--unused-statements");
+    cmd.assert().success().stdout(r#"{"srcRef":{"sourcePath":"examples/stack.rs","lineNumber":15,"column":11,"name":"b","text":"\"Hello from b\"","vars":[]},"variables":{},"stack":[[{"sourcePath":"examples/stack.rs","lineNumber":11,"column":4,"name":"a","text":"b","vars":[]},{"sourcePath":"examples/stack.rs","lineNumber":7,"column":4,"name":"main","text":"a","vars":[]}]]}
+{"type":"unusedStatements","unusedStatements":[{"sourcePath":"examples/stack.rs","lineNumber":6,"column":11,"name":"main","text":"\"Hello from main\"","vars":[]}]}
+"#);
+    Ok(())
+}
+
+#[test]
+fn output_file() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let source = Path::new("examples").join("stack.rs");
+    let log = Path::new("tests")
+        .join("resources")
+        .join("rust")
+        .join("stack.log");
+    let output = std::env::temp_dir().join(format!("log2src-output-{}.json", std::process::id()));
+    cmd.arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("-l")
+        .arg(log.to_str().expect("test case log path is valid"))
+        .arg("-s")
+        .arg("1")
+        .arg("--output-file")
+        .arg(&output);
+    cmd.assert().success().stdout("");
+    let written = std::fs::read_to_string(&output)?;
+    std::fs::remove_file(&output)?;
+    assert_eq!(
+        written,
+        r#"{"srcRef":{"sourcePath":"examples/stack.rs","lineNumber":15,"column":11,"name":"b","text":"\"Hello from b\"","vars":[]},"variables":{},"stack":[[{"sourcePath":"examples/stack.rs","lineNumber":11,"column":4,"name":"a","text":"b","vars":[]},{"sourcePath":"examples/stack.rs","lineNumber":7,"column":4,"name":"main","text":"a","vars":[]}]]}
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn output_file_short() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let source = Path::new("examples").join("stack.rs");
+    let log = Path::new("tests")
+        .join("resources")
+        .join("rust")
+        .join("stack.log");
+    let output =
+        std::env::temp_dir().join(format!("log2src-output-short-{}.json", std::process::id()));
+    cmd.arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("-l")
+        .arg(log.to_str().expect("test case log path is valid"))
+        .arg("-s")
+        .arg("1")
+        .arg("-o")
+        .arg(&output);
+    cmd.assert().success().stdout("");
+    let written = std::fs::read_to_string(&output)?;
+    std::fs::remove_file(&output)?;
+    assert_eq!(
+        written,
+        r#"{"srcRef":{"sourcePath":"examples/stack.rs","lineNumber":15,"column":11,"name":"b","text":"\"Hello from b\"","vars":[]},"variables":{},"stack":[[{"sourcePath":"examples/stack.rs","lineNumber":11,"column":4,"name":"a","text":"b","vars":[]},{"sourcePath":"examples/stack.rs","lineNumber":7,"column":4,"name":"main","text":"a","vars":[]}]]}
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn emit_pattern() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let source = Path::new("examples").join("stack.rs");
+    let log = Path::new("tests")
+        .join("resources")
+        .join("rust")
+        .join("stack.log");
+    cmd.arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("-l")
+        .arg(log.to_str().expect("test case log path is valid"))
+        .arg("-s")
+        .arg("1")
+        .arg("--emit-pattern");
+    cmd.assert().success().stdout(r#"{"srcRef":{"sourcePath":"examples/stack.rs","lineNumber":15,"column":11,"name":"b","text":"\"Hello from b\"","vars":[],"pattern":"Hello from b"},"variables":{},"stack":[[{"sourcePath":"examples/stack.rs","lineNumber":11,"column":4,"name":"a","text":"b","vars":[]},{"sourcePath":"examples/stack.rs","lineNumber":7,"column":4,"name":"main","text":"a","vars":[]}]]}
+"#);
+    Ok(())
+}
+
+#[test]
+fn fail_on_unmatched() -> Result<(), Box<dyn std::error::Error>> {
+    let source = Path::new("examples").join("stack.rs");
+    let log = Path::new("tests")
+        .join("resources")
+        .join("rust")
+        .join("basic.log");
+    let mut cmd = Command::cargo_bin("log2src")?;
+    cmd.arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("-l")
+        .arg(log.to_str().expect("test case log path is valid"))
+        .arg("--fail-on-unmatched");
+    cmd.assert().code(3);
+
+    let mut cmd = Command::cargo_bin("log2src")?;
+    cmd.arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("-l")
+        .arg(log.to_str().expect("test case log path is valid"))
+        .arg("--fail-on-unmatched=3");
+    cmd.assert().success();
+    Ok(())
+}
+
+#[test]
+fn callers() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let source = Path::new("examples").join("basic.rs");
+    let log = Path::new("tests")
+        .join("resources")
+        .join("rust")
+        .join("basic.log");
+    cmd.arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("-l")
+        .arg(log.to_str().expect("test case log path is valid"))
+        .arg("-s")
+        .arg("3")
+        .arg("--callers");
+    cmd.assert().success().stdout(r#"{"srcRef":{"sourcePath":"examples/basic.rs","lineNumber":13,"column":11,"name":"foo","text":"\"Hello from foo i={}\"","vars":["i"]},"variables":{"i":"2"},"stack":[[{"sourcePath":"examples/basic.rs","lineNumber":8,"column":8,"name":"main","text":"foo","vars":[]}]],"callers":[{"sourcePath":"examples/basic.rs","lineNumber":8,"column":8,"name":"main","text":"foo","vars":[]}]}
+"#);
+    Ok(())
+}
+
+#[test]
+fn line_prefix() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let source = Path::new("examples").join("basic.rs");
+    let log = Path::new("tests")
+        .join("resources")
+        .join("rust")
+        .join("container.log");
+    cmd.arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("-l")
+        .arg(log.to_str().expect("test case log path is valid"))
+        .arg("-s")
+        .arg("1")
+        .arg("-e")
+        .arg("2")
+        .arg("--line-prefix")
+        .arg(r"[\w-]+ \| ");
+    cmd.assert().success().stdout(r#"{"srcRef":{"sourcePath":"examples/basic.rs","lineNumber":13,"column":11,"name":"foo","text":"\"Hello from foo i={}\"","vars":["i"]},"variables":{"i":"0"},"stack":[[{"sourcePath":"examples/basic.rs","lineNumber":8,"column":8,"name":"main","text":"foo","vars":[]}]]}
+"#);
+    Ok(())
+}
+
+#[test]
+fn count_matches_per_file() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let source = Path::new("examples").join("basic.rs");
+    let log = Path::new("tests")
+        .join("resources")
+        .join("rust")
+        .join("basic.log");
+    cmd.arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("-l")
+        .arg(log.to_str().expect("test case log path is valid"))
+        .arg("-s")
+        .arg("3")
+        .arg("--count-matches-per-file");
+    cmd.assert().success().stdout(r#"{"srcRef":{"sourcePath":"examples/basic.rs","lineNumber":13,"column":11,"name":"foo","text":"\"Hello from foo i={}\"","vars":["i"]},"variables":{"i":"2"},"stack":[[{"sourcePath":"examples/basic.rs","lineNumber":8,"column":8,"name":"main","text":"foo","vars":[]}]]}
+{"type":"matchesPerFile","matchesPerFile":{"examples/basic.rs":1}}
+"#);
+    Ok(())
+}
+
+#[test]
+fn unmatched_to() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let source = Path::new("examples").join("stack.rs");
+    let log = Path::new("tests")
+        .join("resources")
+        .join("rust")
+        .join("basic.log");
+    let unmatched =
+        std::env::temp_dir().join(format!("log2src-unmatched-{}.log", std::process::id()));
+    cmd.arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("-l")
+        .arg(log.to_str().expect("test case log path is valid"))
+        .arg("--unmatched-to")
+        .arg(&unmatched);
+    cmd.assert().success();
+    let written = std::fs::read_to_string(&unmatched)?;
+    std::fs::remove_file(&unmatched)?;
+    assert_eq!(
+        written,
+        "[2024-05-09T19:58:53Z DEBUG basic] Hello from foo i=0
+[2024-05-09T19:58:53Z DEBUG basic] Hello from foo i=1
+[2024-05-09T19:58:53Z DEBUG basic] Hello from foo i=2
+"
+    );
+    Ok(())
+}
+
+#[test]
+fn overlapping_sources() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let source = Path::new("examples").join("stack.rs");
+    let log = Path::new("tests")
+        .join("resources")
+        .join("rust")
+        .join("stack.log");
+    cmd.arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("-d")
+        .arg(Path::new(".").join(&source))
+        .arg("-l")
+        .arg(log.to_str().expect("test case log path is valid"))
+        .arg("-s")
+        .arg("1")
+        .arg("--unused-statements");
+    cmd.assert().success().stdout(r#"{"srcRef":{"sourcePath":"examples/stack.rs","lineNumber":15,"column":11,"name":"b","text":"\"Hello from b\"","vars":[]},"variables":{},"stack":[[{"sourcePath":"examples/stack.rs","lineNumber":11,"column":4,"name":"a","text":"b","vars":[]},{"sourcePath":"examples/stack.rs","lineNumber":7,"column":4,"name":"main","text":"a","vars":[]}]]}
+{"type":"unusedStatements","unusedStatements":[{"sourcePath":"examples/stack.rs","lineNumber":6,"column":11,"name":"main","text":"\"Hello from main\"","vars":[]}]}
+"#);
+    Ok(())
+}
+
+#[test]
+fn json_pretty() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let source = Path::new("examples").join("basic.rs");
+    let log = Path::new("tests")
+        .join("resources")
+        .join("rust")
+        .join("basic.log");
+    cmd.arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("-l")
+        .arg(log.to_str().expect("test case log path is valid"))
+        .arg("-s")
+        .arg("2")
+        .arg("-e")
+        .arg("4")
+        .arg("--json-pretty");
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let records = serde_json::Deserializer::from_str(&stdout)
+        .into_iter::<serde_json::Value>()
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["variables"]["i"], "1");
+    assert_eq!(records[1]["variables"]["i"], "2");
+    // each record is indented, and starts and ends on a line of its own
+    assert!(stdout.starts_with("{\n  \"srcRef\": {\n"));
+    assert_eq!(stdout.matches("\n}\n").count(), 2);
+    assert!(stdout.ends_with("\n}\n"));
+    Ok(())
+}
+
+#[test]
+fn git_rev() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = std::env::temp_dir().join(format!("log2src-git-rev-{}", std::process::id()));
+    std::fs::create_dir_all(repo.join("src"))?;
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(&repo)
+            .args([
+                "-c",
+                "user.name=log2src",
+                "-c",
+                "user.email=log2src@example.com",
+            ])
+            .args(args)
+            .output()
+            .expect("git runs")
+            .status;
+        assert!(status.success(), "git {:?}", args);
+    };
+    git(&["init", "-q"]);
+    let source = repo.join("src").join("main.rs");
+    std::fs::write(
+        &source,
+        "fn main() {\n    debug!(\"starting v{}\", version);\n}\n",
+    )?;
+    git(&["add", "."]);
+    git(&["commit", "-q", "-m", "first"]);
+    std::fs::write(
+        &source,
+        "fn main() {\n    // startup\n    debug!(\"booting version {}\", version);\n}\n",
+    )?;
+    git(&["commit", "-q", "-a", "-m", "second"]);
+    let log = repo.join("old.log");
+    std::fs::write(&log, "[2024-05-09T19:58:53Z DEBUG app] starting v1.2\n")?;
+
+    let mut cmd = Command::cargo_bin("log2src")?;
+    cmd.arg("-d").arg(repo.join("src")).arg("-l").arg(&log);
+    let current = cmd.output()?;
+    let mut cmd = Command::cargo_bin("log2src")?;
+    cmd.arg("-d")
+        .arg(repo.join("src"))
+        .arg("-l")
+        .arg(&log)
+        .arg("--git-rev")
+        .arg("HEAD~1");
+    let old = cmd.output()?;
+    std::fs::remove_dir_all(&repo)?;
+
+    assert!(current.status.success());
+    assert!(String::from_utf8(current.stdout)?.starts_with(r#"{"srcRef":null"#));
+    assert!(old.status.success());
+    let expected = format!(
+        r#"{{"srcRef":{{"sourcePath":{},"lineNumber":2,"column":11,"name":"main","text":"\"starting v{{}}\"","vars":["version"]}},"variables":{{"version":"1.2"}},"stack":[]}}
+"#,
+        serde_json::to_string(&source)?
+    );
+    assert_eq!(String::from_utf8(old.stdout)?, expected);
+    Ok(())
+}
+
+#[test]
+fn first_hit_only() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let source = Path::new("examples").join("basic.rs");
+    let log = Path::new("tests")
+        .join("resources")
+        .join("rust")
+        .join("basic.log");
+    cmd.arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("-l")
+        .arg(log.to_str().expect("test case log path is valid"))
+        .arg("--first-hit-only");
+    cmd.assert().success().stdout(r#"{"srcRef":{"sourcePath":"examples/basic.rs","lineNumber":6,"column":11,"name":"main","text":"\"Hello from main\"","vars":[]},"variables":{},"stack":[[{"sourcePath":"examples/basic.rs","lineNumber":8,"column":8,"name":"main","text":"foo","vars":[]}]]}
+{"srcRef":{"sourcePath":"examples/basic.rs","lineNumber":13,"column":11,"name":"foo","text":"\"Hello from foo i={}\"","vars":["i"]},"variables":{"i":"0"},"stack":[[{"sourcePath":"examples/basic.rs","lineNumber":8,"column":8,"name":"main","text":"foo","vars":[]}]]}
+"#);
+    Ok(())
+}
+
+#[test]
+fn summary() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let source = Path::new("examples").join("basic.rs");
+    let log = Path::new("tests")
+        .join("resources")
+        .join("rust")
+        .join("basic.log");
+    cmd.arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("-l")
+        .arg(log.to_str().expect("test case log path is valid"))
+        .arg("-s")
+        .arg("1")
+        .arg("--summary");
+    cmd.assert()
+        .success()
+        .stderr("log2src: 3 log lines, 3 matched, 0 unmatched, 1 source files hit\n");
+    Ok(())
+}
+
+// How long a follow test waits for the next mapping before giving up on it.
+const FOLLOW_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Reads the child's output a line at a time on another thread, so the test can wait for each
+// mapping as it's written, with a bound, instead of sleeping and hoping it's there.
+fn output_lines(child: &mut Child) -> Receiver<String> {
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+const FOLLOW_MAIN: &str = r#"{"srcRef":{"sourcePath":"examples/basic.rs","lineNumber":6,"column":11,"name":"main","text":"\"Hello from main\"","vars":[]},"variables":{},"stack":[[{"sourcePath":"examples/basic.rs","lineNumber":8,"column":8,"name":"main","text":"foo","vars":[]}]]}"#;
+const FOLLOW_FOO: &str = r#"{"srcRef":{"sourcePath":"examples/basic.rs","lineNumber":13,"column":11,"name":"foo","text":"\"Hello from foo i={}\"","vars":["i"]},"variables":{"i":"7"},"stack":[[{"sourcePath":"examples/basic.rs","lineNumber":8,"column":8,"name":"main","text":"foo","vars":[]}]]}"#;
+
+#[test]
+fn follow() -> Result<(), Box<dyn std::error::Error>> {
+    let source = Path::new("examples").join("basic.rs");
+    let log = std::env::temp_dir().join(format!("log2src-follow-{}.log", std::process::id()));
+    std::fs::write(&log, "[2024-05-09T19:58:53Z DEBUG basic] Hello from main\n")?;
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let mut child = cmd
+        .arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("-l")
+        .arg(&log)
+        .arg("--follow")
+        .arg("-e")
+        .arg("2")
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let lines = output_lines(&mut child);
+    assert_eq!(lines.recv_timeout(FOLLOW_TIMEOUT)?, FOLLOW_MAIN);
+
+    // the line is written in two pieces, and only mapped once it's finished
+    let mut appender = std::fs::OpenOptions::new().append(true).open(&log)?;
+    appender.write_all(b"[2024-05-09T19:58:53Z DEBUG basic] Hello fr")?;
+    appender.flush()?;
+    appender.write_all(b"om foo i=7\n")?;
+    appender.flush()?;
+    assert_eq!(lines.recv_timeout(FOLLOW_TIMEOUT)?, FOLLOW_FOO);
+
+    // the END line stops it, which closes the output
+    assert_eq!(
+        lines.recv_timeout(FOLLOW_TIMEOUT),
+        Err(RecvTimeoutError::Disconnected)
+    );
+    assert!(child.wait()?.success());
+    std::fs::remove_file(&log)?;
+    Ok(())
+}
+
+#[test]
+fn follow_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    let source = Path::new("examples").join("basic.rs");
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let mut child = cmd
+        .arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("--follow")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let lines = output_lines(&mut child);
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+
+    // each line is mapped while the pipe is still open
+    stdin.write_all(b"[2024-05-09T19:58:53Z DEBUG basic] Hello from main\n")?;
+    stdin.flush()?;
+    assert_eq!(lines.recv_timeout(FOLLOW_TIMEOUT)?, FOLLOW_MAIN);
+    stdin.write_all(b"[2024-05-09T19:58:53Z DEBUG basic] Hello from foo i=7")?;
+    stdin.flush()?;
+
+    // closing the pipe ends the log, and its unfinished last line with it
+    drop(stdin);
+    assert_eq!(lines.recv_timeout(FOLLOW_TIMEOUT)?, FOLLOW_FOO);
+    assert_eq!(
+        lines.recv_timeout(FOLLOW_TIMEOUT),
+        Err(RecvTimeoutError::Disconnected)
+    );
+    assert!(child.wait()?.success());
+    Ok(())
+}
+
+#[test]
+fn follow_watch() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("log2src-watch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let source = dir.join("main.rs");
+    std::fs::write(
+        &source,
+        "fn main() {\n    let version = 1;\n    debug!(\"starting version {}\", version);\n}\n",
+    )?;
+    let log = dir.join("app.log");
+    std::fs::write(&log, "starting version 1\n")?;
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let mut child = cmd
+        .arg("-d")
+        .arg(&dir)
+        .arg("-l")
+        .arg(&log)
+        .arg("--follow")
+        .arg("--watch")
+        .arg("-e")
+        .arg("2")
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let lines = output_lines(&mut child);
+    assert!(lines
+        .recv_timeout(FOLLOW_TIMEOUT)?
+        .contains(r#""text":"\"starting version {}\"""#));
+
+    // the statement changes, and a line it logs after the next check is matched against it
+    std::fs::write(
+        &source,
+        "fn main() {\n    let version = 2;\n    debug!(\"booting version {}\", version);\n}\n",
+    )?;
+    thread::sleep(Duration::from_millis(1500));
+    let mut appender = std::fs::OpenOptions::new().append(true).open(&log)?;
+    appender.write_all(b"booting version 2\n")?;
+    appender.flush()?;
+    assert!(lines
+        .recv_timeout(FOLLOW_TIMEOUT)?
+        .contains(r#""text":"\"booting version {}\"""#));
+
+    assert_eq!(
+        lines.recv_timeout(FOLLOW_TIMEOUT),
+        Err(RecvTimeoutError::Disconnected)
+    );
+    assert!(child.wait()?.success());
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn output_protobuf() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let source = Path::new("examples").join("basic.rs");
+    let log = Path::new("tests")
+        .join("resources")
+        .join("rust")
+        .join("basic.log");
+    cmd.arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("-l")
+        .arg(log.to_str().expect("test case log path is valid"))
+        .arg("-s")
+        .arg("1")
+        .arg("-e")
+        .arg("2")
+        .arg("--output")
+        .arg("protobuf");
+    #[cfg(feature = "protobuf")]
+    {
+        use log2src::protobuf::LogMappingMessage;
+        use prost::Message;
+
+        let output = cmd.output()?;
+        assert!(output.status.success());
+        let mut buf = output.stdout.as_slice();
+        let mapping = LogMappingMessage::decode_length_delimited(&mut buf)?;
+        assert!(buf.is_empty());
+        let src_ref = mapping.src_ref.expect("the line maps");
+        assert_eq!(src_ref.source_path, "examples/basic.rs");
+        assert_eq!(src_ref.line_number, 13);
+        assert_eq!(mapping.variables.get("i"), Some(&String::from("0")));
+    }
+    #[cfg(not(feature = "protobuf"))]
+    cmd.assert().failure();
+    Ok(())
+}
+
+#[test]
+fn output_flat_ndjson() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let source = Path::new("examples").join("basic.rs");
+    let log = Path::new("tests")
+        .join("resources")
+        .join("rust")
+        .join("basic.log");
+    cmd.arg("-d")
+        .arg(source.to_str().expect("test case path is valid"))
+        .arg("-l")
+        .arg(log.to_str().expect("test case log path is valid"))
+        .arg("-s")
+        .arg("1")
+        .arg("-e")
+        .arg("2")
+        .arg("--output")
+        .arg("flat-ndjson");
+    cmd.assert().success().stdout(r#"{"line":"[2024-05-09T19:58:53Z DEBUG basic] Hello from foo i=0","timestamp":"2024-05-09T19:58:53Z","level":"DEBUG","thread":null,"matched":true,"confidence":0.9444444444444444,"sourcePath":"examples/basic.rs","lineNumber":13,"functionName":"foo","variables":"{\"i\":\"0\"}","repeated":null,"truncated":false,"indexTruncated":false}
+"#);
+    Ok(())
+}
+
+#[test]
+fn max_candidates_after_only() -> Result<(), Box<dyn std::error::Error>> {
+    let log = Path::new("tests")
+        .join("resources")
+        .join("rust")
+        .join("basic.log");
+    // the other examples come first, so a cap before --only would leave nothing of basic.rs
+    for (max, line_number, index_truncated) in [("2", Some(13), None), ("1", None, Some(true))] {
+        let mut cmd = Command::cargo_bin("log2src")?;
+        cmd.arg("-d")
+            .arg("examples")
+            .arg("-l")
+            .arg(log.to_str().expect("test case log path is valid"))
+            .arg("-s")
+            .arg("1")
+            .arg("-e")
+            .arg("2")
+            .arg("--only")
+            .arg("basic.rs")
+            .arg("--max-candidates")
+            .arg(max);
+        let output = cmd.output()?;
+        assert!(output.status.success());
+        let record = serde_json::from_slice::<serde_json::Value>(&output.stdout)?;
+        assert_eq!(record["srcRef"]["lineNumber"].as_u64(), line_number);
+        assert_eq!(record["indexTruncated"].as_bool(), index_truncated);
+    }
+    Ok(())
+}
+
+#[test]
+fn reports_need_json_output() -> Result<(), Box<dyn std::error::Error>> {
+    let source = Path::new("examples").join("basic.rs");
+    let log = Path::new("tests")
+        .join("resources")
+        .join("rust")
+        .join("basic.log");
+    for (output, report) in [
+        ("sarif", "--unused-statements"),
+        ("flat-ndjson", "--count-matches-per-file"),
+    ] {
+        let mut cmd = Command::cargo_bin("log2src")?;
+        cmd.arg("-d")
+            .arg(source.to_str().expect("test case path is valid"))
+            .arg("-l")
+            .arg(log.to_str().expect("test case log path is valid"))
+            .arg("--output")
+            .arg(output)
+            .arg(report);
+        cmd.assert().code(2);
+    }
+    Ok(())
+}
@@ -0,0 +1,29 @@
+use assert_cmd::prelude::*;
+use std::{path::Path, process::Command};
+
+#[test]
+fn echo_and_printf() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("log2src")?;
+    let deploy_source = Path::new("tests").join("shell").join("deploy.sh");
+    let deploy_log = Path::new("tests")
+        .join("resources")
+        .join("shell")
+        .join("deploy.log");
+    cmd.arg("-d")
+        .arg(
+            deploy_source
+                .to_str()
+                .expect("test case source code exists"),
+        )
+        .arg("-l")
+        .arg(deploy_log.to_str().expect("test case log exists"))
+        // the flat output writes the variables in order
+        .arg("--output")
+        .arg("flat-ndjson");
+    cmd.assert().success().stdout(r#"{"line":"deploying web","timestamp":null,"level":null,"thread":null,"matched":true,"confidence":0.7692307692307693,"sourcePath":"tests/shell/deploy.sh","lineNumber":5,"functionName":"deploy","variables":"{\"app\":\"web\"}","repeated":null,"truncated":false,"indexTruncated":false}
+{"line":"took 12 seconds","timestamp":null,"level":null,"thread":null,"matched":true,"confidence":0.8666666666666667,"sourcePath":"tests/shell/deploy.sh","lineNumber":6,"functionName":"deploy","variables":"{\"2\":\"12\"}","repeated":null,"truncated":false,"indexTruncated":false}
+{"line":"deploying api","timestamp":null,"level":null,"thread":null,"matched":true,"confidence":0.7692307692307693,"sourcePath":"tests/shell/deploy.sh","lineNumber":5,"functionName":"deploy","variables":"{\"app\":\"api\"}","repeated":null,"truncated":false,"indexTruncated":false}
+{"line":"took 3 seconds","timestamp":null,"level":null,"thread":null,"matched":true,"confidence":0.9285714285714286,"sourcePath":"tests/shell/deploy.sh","lineNumber":6,"functionName":"deploy","variables":"{\"2\":\"3\"}","repeated":null,"truncated":false,"indexTruncated":false}
+"#);
+    Ok(())
+}